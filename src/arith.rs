@@ -0,0 +1,418 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArithError {
+    #[error("syntax error: {0}")]
+    Syntax(String),
+    #[error("division by 0")]
+    DivisionByZero,
+    #[error("exponent less than 0")]
+    NegativeExponent,
+    #[error("expression recursion level exceeded")]
+    Recursion,
+    #[error("{0}: unbound variable")]
+    Unbound(String),
+}
+
+/// Access to the shell variables an expression reads and assigns.
+pub trait Variables {
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: i64);
+    /// Whether reading a variable that is not set is an error, which is
+    /// `set -u`.
+    fn nounset(&self) -> bool;
+}
+
+/// Evaluates an integer arithmetic expression, as found in `$((...))`,
+/// `((...))` and `let`.
+pub fn eval(expr: &str, vars: &mut dyn Variables) -> Result<i64, ArithError> {
+    Evaluator { vars, depth: 0 }.eval_str(expr)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Question,
+    Colon,
+}
+
+// longest operators first so that e.g. `<<=` wins over `<<` and `<`
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "=", ",",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Tok>, ArithError> {
+    let mut tokens = vec![];
+    let mut rest = expr;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#' || c == '_' || c == '@'))
+                .unwrap_or(rest.len());
+            tokens.push(Tok::Num(parse_number(&rest[..len])?));
+            rest = &rest[len..];
+            continue;
+        }
+
+        // `$name` is accepted as a plain variable reference
+        let name_start = usize::from(c == '$');
+        if rest[name_start..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let len = rest[name_start..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |len| name_start + len);
+            tokens.push(Tok::Ident(rest[name_start..len].to_owned()));
+            rest = &rest[len..];
+            continue;
+        }
+
+        let (tok, len) = match c {
+            '(' => (Tok::LParen, 1),
+            ')' => (Tok::RParen, 1),
+            '?' => (Tok::Question, 1),
+            ':' => (Tok::Colon, 1),
+            _ => match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => (Tok::Op(op), op.len()),
+                None => return Err(ArithError::Syntax(format!("invalid character: {c}"))),
+            },
+        };
+        tokens.push(tok);
+        rest = &rest[len..];
+    }
+
+    Ok(tokens)
+}
+
+/// Parses decimal, octal (`017`), hexadecimal (`0x1f`) and `base#digits`
+/// integer constants.
+fn parse_number(text: &str) -> Result<i64, ArithError> {
+    let invalid = || ArithError::Syntax(format!("invalid number: {text}"));
+
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=64).contains(&base) {
+            return Err(invalid());
+        }
+        (base, digits)
+    } else if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            // bases up to 36 are case insensitive
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+
+    Ok(value)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    /// `++x` or `--x`
+    PreIncrement(&'static str, String),
+    /// `x++` or `x--`
+    PostIncrement(&'static str, String),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Assign(&'static str, String, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+}
+
+/// Binding power of binary operators, higher binds tighter.
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "," => 1,
+        "||" => 4,
+        "&&" => 5,
+        "|" => 6,
+        "^" => 7,
+        "&" => 8,
+        "==" | "!=" => 9,
+        "<" | ">" | "<=" | ">=" => 10,
+        "<<" | ">>" => 11,
+        "+" | "-" => 12,
+        "*" | "/" | "%" => 13,
+        "**" => 14,
+        _ => return None,
+    })
+}
+
+const ASSIGNMENT_PRECEDENCE: u8 = 2;
+const TERNARY_PRECEDENCE: u8 = 3;
+
+fn is_assignment(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "*=" | "/=" | "%=" | "+=" | "-=" | "<<=" | ">>=" | "&=" | "^=" | "|="
+    )
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, expected: Tok) -> Result<(), ArithError> {
+        match self.next() {
+            Some(tok) if tok == expected => Ok(()),
+            tok => Err(unexpected(tok)),
+        }
+    }
+
+    fn parse_expr(&mut self, min_precedence: u8) -> Result<Expr, ArithError> {
+        let mut lhs = self.parse_unary()?;
+
+        loop {
+            match self.peek() {
+                Some(Tok::Op(op)) if is_assignment(op) => {
+                    if min_precedence > ASSIGNMENT_PRECEDENCE {
+                        break;
+                    }
+                    let op = *op;
+                    let Expr::Var(name) = lhs else {
+                        return Err(ArithError::Syntax(format!(
+                            "attempted assignment to non-variable ({op})"
+                        )));
+                    };
+                    self.next();
+                    // right associative
+                    let rhs = self.parse_expr(ASSIGNMENT_PRECEDENCE)?;
+                    lhs = Expr::Assign(op, name, Box::new(rhs));
+                }
+                Some(Tok::Question) => {
+                    if min_precedence > TERNARY_PRECEDENCE {
+                        break;
+                    }
+                    self.next();
+                    let then = self.parse_expr(ASSIGNMENT_PRECEDENCE)?;
+                    self.expect(Tok::Colon)?;
+                    let otherwise = self.parse_expr(TERNARY_PRECEDENCE)?;
+                    lhs = Expr::Ternary(Box::new(lhs), Box::new(then), Box::new(otherwise));
+                }
+                Some(Tok::Op(op)) => {
+                    let op = *op;
+                    let Some(precedence) = binary_precedence(op) else {
+                        break;
+                    };
+                    if precedence < min_precedence {
+                        break;
+                    }
+                    self.next();
+                    // `**` is right associative, everything else left
+                    let next_min = if op == "**" {
+                        precedence
+                    } else {
+                        precedence + 1
+                    };
+                    let rhs = self.parse_expr(next_min)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ArithError> {
+        match self.next() {
+            Some(Tok::Op(op @ ("++" | "--"))) => match self.next() {
+                Some(Tok::Ident(name)) => Ok(Expr::PreIncrement(op, name)),
+                tok => Err(unexpected(tok)),
+            },
+            Some(Tok::Op(op @ ("!" | "~" | "+" | "-"))) => {
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Some(Tok::Num(n)) => Ok(Expr::Num(n)),
+            Some(Tok::Ident(name)) => match self.peek() {
+                Some(Tok::Op(op @ ("++" | "--"))) => {
+                    let op = *op;
+                    self.next();
+                    Ok(Expr::PostIncrement(op, name))
+                }
+                _ => Ok(Expr::Var(name)),
+            },
+            Some(Tok::LParen) => {
+                let expr = self.parse_expr(0)?;
+                self.expect(Tok::RParen)?;
+                Ok(expr)
+            }
+            tok => Err(unexpected(tok)),
+        }
+    }
+}
+
+fn unexpected(tok: Option<Tok>) -> ArithError {
+    ArithError::Syntax(match tok {
+        Some(tok) => format!("unexpected token: {tok:?}"),
+        None => "operand expected".to_owned(),
+    })
+}
+
+struct Evaluator<'a> {
+    vars: &'a mut dyn Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval_str(&mut self, expr: &str) -> Result<i64, ArithError> {
+        let tokens = tokenize(expr)?;
+        if tokens.is_empty() {
+            return Ok(0);
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let ast = parser.parse_expr(0)?;
+        if let Some(tok) = parser.next() {
+            return Err(unexpected(Some(tok)));
+        }
+
+        self.eval(&ast)
+    }
+
+    /// The value of a variable; non-numeric values are evaluated as
+    /// expressions themselves, and unset variables are 0 unless `nounset`.
+    fn var(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = match self.vars.get(name) {
+            Some(value) => value,
+            None if self.vars.nounset() => return Err(ArithError::Unbound(name.to_owned())),
+            None => String::new(),
+        };
+        if let Ok(n) = value.trim().parse() {
+            return Ok(n);
+        }
+
+        if self.depth >= 64 {
+            return Err(ArithError::Recursion);
+        }
+        self.depth += 1;
+        let result = self.eval_str(&value);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        Ok(match expr {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => self.var(name)?,
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                match *op {
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    "-" => value.wrapping_neg(),
+                    _ => value,
+                }
+            }
+            Expr::PreIncrement(op, name) => {
+                let value = apply(if *op == "++" { "+" } else { "-" }, self.var(name)?, 1)?;
+                self.vars.set(name, value);
+                value
+            }
+            Expr::PostIncrement(op, name) => {
+                let value = self.var(name)?;
+                self.vars
+                    .set(name, apply(if *op == "++" { "+" } else { "-" }, value, 1)?);
+                value
+            }
+            Expr::Binary("&&", lhs, rhs) => (self.eval(lhs)? != 0 && self.eval(rhs)? != 0) as i64,
+            Expr::Binary("||", lhs, rhs) => (self.eval(lhs)? != 0 || self.eval(rhs)? != 0) as i64,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                apply(op, lhs, rhs)?
+            }
+            Expr::Assign(op, name, rhs) => {
+                let rhs = self.eval(rhs)?;
+                let value = match op.strip_suffix('=').unwrap_or_default() {
+                    "" => rhs,
+                    op => apply(op, self.var(name)?, rhs)?,
+                };
+                self.vars.set(name, value);
+                value
+            }
+            Expr::Ternary(cond, then, otherwise) => {
+                if self.eval(cond)? != 0 {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+        })
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+    Ok(match op {
+        "," => rhs,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err(ArithError::DivisionByZero),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" => {
+            if rhs < 0 {
+                return Err(ArithError::NegativeExponent);
+            }
+            lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32)
+        }
+        _ => unreachable!("unknown operator {op}"),
+    })
+}
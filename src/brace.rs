@@ -0,0 +1,225 @@
+use crate::scanner::{Word, WordPart, substitution_len};
+
+/// A word broken into unquoted characters, which may take part in brace
+/// expansion, and quoted parts or substitutions, which never do.
+#[derive(Debug, Clone)]
+enum Segment {
+    Char(char),
+    Quoted(WordPart),
+}
+
+/// Performs bash-style brace expansion on `word`, e.g. `file.{rs,toml}`,
+/// `img{1..10}`, `{a..z..2}` or `{01..12}`. Braces that do not form a valid
+/// list or sequence are left as they are.
+pub fn expand(word: &Word) -> Vec<Word> {
+    let segments = split(word);
+    expand_segments(&segments).into_iter().map(join).collect()
+}
+
+fn split(word: &Word) -> Vec<Segment> {
+    let mut segments = vec![];
+    for part in &word.parts {
+        match part {
+            WordPart::Unquoted(s) => {
+                let mut i = 0;
+                while let Some(c) = s[i..].chars().next() {
+                    match substitution_len(&s[i..]) {
+                        Some(len) => {
+                            let raw = s[i..i + len].to_owned();
+                            segments.push(Segment::Quoted(WordPart::Unquoted(raw)));
+                            i += len;
+                        }
+                        None => {
+                            segments.push(Segment::Char(c));
+                            i += c.len_utf8();
+                        }
+                    }
+                }
+            }
+            quoted => segments.push(Segment::Quoted(quoted.clone())),
+        }
+    }
+    segments
+}
+
+fn join(segments: Vec<Segment>) -> Word {
+    let mut parts = vec![];
+    let mut unquoted = String::new();
+
+    for segment in segments {
+        match segment {
+            Segment::Char(c) => unquoted.push(c),
+            Segment::Quoted(part) => {
+                if !unquoted.is_empty() {
+                    parts.push(WordPart::Unquoted(std::mem::take(&mut unquoted)));
+                }
+                parts.push(part);
+            }
+        }
+    }
+    if !unquoted.is_empty() {
+        parts.push(WordPart::Unquoted(unquoted));
+    }
+
+    Word::new(parts)
+}
+
+fn is_char(segment: &Segment, c: char) -> bool {
+    matches!(segment, Segment::Char(x) if *x == c)
+}
+
+fn expand_segments(segments: &[Segment]) -> Vec<Vec<Segment>> {
+    let mut search = 0;
+
+    while let Some(open) = (search..segments.len()).find(|&i| is_char(&segments[i], '{')) {
+        let Some(close) = matching_close(segments, open) else {
+            search = open + 1;
+            continue;
+        };
+
+        if let Some(alternatives) = alternatives(&segments[open + 1..close]) {
+            let preamble = &segments[..open];
+            let postscripts = expand_segments(&segments[close + 1..]);
+
+            let mut words = vec![];
+            for alternative in alternatives {
+                for postscript in &postscripts {
+                    let mut word = preamble.to_vec();
+                    word.extend(alternative.iter().cloned());
+                    word.extend(postscript.iter().cloned());
+                    words.push(word);
+                }
+            }
+            return words;
+        }
+
+        search = open + 1;
+    }
+
+    vec![segments.to_vec()]
+}
+
+/// Finds the unquoted `}` closing the brace at `open`.
+fn matching_close(segments: &[Segment], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, segment) in segments.iter().enumerate().skip(open) {
+        if is_char(segment, '{') {
+            depth += 1;
+        } else if is_char(segment, '}') {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Expands the inside of a brace pair, either a comma separated list or a
+/// sequence expression.
+fn alternatives(inner: &[Segment]) -> Option<Vec<Vec<Segment>>> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, segment) in inner.iter().enumerate() {
+        if is_char(segment, '{') {
+            depth += 1;
+        } else if is_char(segment, '}') {
+            depth -= 1;
+        } else if depth == 0 && is_char(segment, ',') {
+            items.push(&inner[start..i]);
+            start = i + 1;
+        }
+    }
+
+    if !items.is_empty() {
+        items.push(&inner[start..]);
+        return Some(items.into_iter().flat_map(expand_segments).collect());
+    }
+
+    let text: Option<String> = inner
+        .iter()
+        .map(|segment| match segment {
+            Segment::Char(c) => Some(*c),
+            Segment::Quoted(_) => None,
+        })
+        .collect();
+
+    Some(
+        sequence(&text?)?
+            .into_iter()
+            .map(|item| item.chars().map(sequence_char).collect())
+            .collect(),
+    )
+}
+
+/// A character of a generated sequence item. Those that later expansions
+/// would read as syntax, like the `` ` `` in `{X..b}`, are escaped.
+fn sequence_char(c: char) -> Segment {
+    match c {
+        '`' | '$' | '\\' => Segment::Quoted(WordPart::Escaped(c)),
+        c => Segment::Char(c),
+    }
+}
+
+/// Expands `x..y` or `x..y..step` where `x` and `y` are both integers or
+/// both single letters.
+pub fn sequence(expr: &str) -> Option<Vec<String>> {
+    let mut fields = expr.split("..");
+    let (start, end) = (fields.next()?, fields.next()?);
+    let step = match fields.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(x), Ok(y)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        return Some(
+            range(x, y, step)
+                .map(|n| {
+                    if n < 0 {
+                        format!("-{:0>width$}", -n, width = width.saturating_sub(1))
+                    } else {
+                        format!("{n:0>width$}")
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(x), None, Some(y), None) if x.is_ascii_alphabetic() && y.is_ascii_alphabetic() => {
+            Some(
+                range(x as i64, y as i64, step)
+                    .map(|c| char::from(c as u8).to_string())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+fn range(x: i64, y: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = x.abs_diff(y) / step + 1;
+    let step = if x <= y { step as i64 } else { -(step as i64) };
+    (0..count as i64).map(move |i| x + i * step)
+}
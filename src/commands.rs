@@ -1,131 +1,76 @@
-use std::{env, path::PathBuf};
+use std::{collections::HashMap, env, rc::Rc};
 
-use crate::scanner::Token;
+use crate::parser::Function;
 
-#[derive(Debug)]
-pub enum CommandIO {
-    Std,
-    File(PathBuf),
-}
-
-#[derive(Debug)]
+/// A command to run, resolved from its expanded words.
+#[derive(Debug, Clone)]
 pub enum Command {
-    Exit,
+    Exit(Vec<String>),
     Help,
     Cd(Vec<String>),
     Pwd(Vec<String>),
-    SetVar(String, String),
     GetVar(Vec<String>),
     ListVars,
-    External {
-        args: Vec<String>,
-        input: CommandIO,
-        output: CommandIO,
-    },
+    Set(Vec<String>),
+    Shopt(Vec<String>),
+    Let(Vec<String>),
+    Jobs(Vec<String>),
+    Fg(Vec<String>),
+    Bg(Vec<String>),
+    Wait(Vec<String>),
+    Disown(Vec<String>),
+    Break(Vec<String>),
+    Continue(Vec<String>),
+    Local(Vec<String>),
+    Return(Vec<String>),
+    Source(Vec<String>),
+    Shift(Vec<String>),
+    Export(Vec<String>),
+    Unset(Vec<String>),
+    Functions(Vec<String>),
+    /// A call of a shell function, with the function name first.
+    Call(Vec<String>),
+    External(Vec<String>),
     Wish,
-    Error(String),
 }
 
+pub type Functions = HashMap<String, Rc<Function>>;
+
 impl Command {
-    pub fn from(args: Vec<String>, input: CommandIO, output: CommandIO) -> Command {
+    /// Resolves expanded words to the command they name. Functions come
+    /// first, then builtins, and anything else is looked up in `PATH`.
+    pub fn from(args: Vec<String>, functions: &Functions) -> Command {
+        if functions.contains_key(&args[0]) {
+            return Command::Call(args);
+        }
+
         match args.first().unwrap().as_str() {
-            "exit" => Command::Exit,
+            "exit" => Command::Exit(args),
             "cd" => Command::Cd(args),
             "pwd" => Command::Pwd(args),
             "lsv" => Command::ListVars,
             "get" => Command::GetVar(args),
+            "set" => Command::Set(args),
+            "shopt" => Command::Shopt(args),
+            "let" => Command::Let(args),
+            "jobs" => Command::Jobs(args),
+            "fg" => Command::Fg(args),
+            "bg" => Command::Bg(args),
+            "wait" => Command::Wait(args),
+            "disown" => Command::Disown(args),
+            "break" => Command::Break(args),
+            "continue" => Command::Continue(args),
+            "local" => Command::Local(args),
+            "return" => Command::Return(args),
+            "source" | "." => Command::Source(args),
+            "shift" => Command::Shift(args),
+            "export" => Command::Export(args),
+            "unset" => Command::Unset(args),
+            "functions" => Command::Functions(args),
             "help" => Command::Help,
             "wish" => Command::Wish,
-            _ => Command::External {
-                args,
-                input,
-                output,
-            },
-        }
-    }
-
-    pub fn process_input(tokens: Vec<Token>) -> Vec<Command> {
-        let mut cmds = vec![];
-
-        let mut tokens = tokens.iter().peekable();
-
-        // consume all variable assignments
-        while let Some(Token::Literal(l)) = tokens.peek() {
-            if let Some((k, v)) = l.split_once("=") {
-                cmds.push(Command::SetVar(k.to_owned(), v.to_owned()));
-                tokens.next();
-            } else {
-                break;
-            }
+            _ => Command::External(args),
         }
-
-        cmds.extend(Self::build_piped_commands(tokens));
-
-        cmds
-    }
-
-    fn build_piped_commands<'a>(tokens: impl Iterator<Item = &'a Token>) -> Vec<Command> {
-        let mut cmds = vec![];
-
-        let mut curr_cmd_args = vec![];
-        let mut input = CommandIO::Std;
-        let mut output = CommandIO::Std;
-
-        let mut tokens = tokens.peekable();
-
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Pipe => {
-                    if curr_cmd_args.is_empty() {
-                        cmds.push(Command::Error("syntax error: | |".to_owned()));
-                        curr_cmd_args.clear();
-                        break;
-                    }
-                    cmds.push(Command::from(curr_cmd_args.clone(), input, output));
-                    curr_cmd_args.clear();
-                    input = CommandIO::Std;
-                    output = CommandIO::Std;
-                }
-                Token::Literal(l) => {
-                    curr_cmd_args.push(l.to_owned());
-                }
-                Token::Greater => {
-                    if let Some(Token::Literal(path)) = tokens.peek() {
-                        // path
-                        let path = PathBuf::from(path);
-                        output = CommandIO::File(path);
-                        tokens.next();
-                    } else {
-                        cmds.push(Command::Error(
-                            "syntax error: no path provided after >".to_owned(),
-                        ));
-                        curr_cmd_args.clear();
-                        break;
-                    }
-                }
-                Token::Less => {
-                    if let Some(Token::Literal(path)) = tokens.peek() {
-                        // path
-                        let path = PathBuf::from(path);
-                        input = CommandIO::File(path);
-                        tokens.next();
-                    } else {
-                        cmds.push(Command::Error(
-                            "syntax error: no path provided after <".to_owned(),
-                        ));
-                        curr_cmd_args.clear();
-                        break;
-                    }
-                }
-                Token::Eof => break,
-            }
-        }
-        // last one
-        if !curr_cmd_args.is_empty() {
-            cmds.push(Command::from(curr_cmd_args, input, output));
-        }
-
-        cmds
     }
 }
 
@@ -135,12 +80,41 @@ pub mod builtins {
     pub fn help() {
         let help_info = vec![
             ("help", "shows this message"),
-            ("exit", "terminates the shell"),
+            ("exit [n]", "terminates the shell with status n"),
             ("wish", "enters wish mode"),
             ("cd [dir]", "change directory to [dir]"),
             ("pwd", "print current working directory"),
             ("lsv", "list all variables"),
             ("get [var]", "print a variable [var]"),
+            (
+                "set [-eux|+eux] [-o|+o opt] [--] [args]",
+                "set, unset or list shell options, or set $1...",
+            ),
+            ("shopt [-s|-u] [opt]", "set, unset or show glob options"),
+            (
+                "let [expr]",
+                "evaluate arithmetic expressions, also ((expr))",
+            ),
+            ("jobs [-l|-p] [job]", "list background and stopped jobs"),
+            ("fg [job]", "continue a job in the foreground"),
+            ("bg [job]", "continue a stopped job in the background"),
+            ("wait [job|pid]", "wait for jobs to finish"),
+            ("disown [-a] [job]", "forget a job"),
+            ("break [n]", "leave n enclosing loops"),
+            ("continue [n]", "start the next iteration of the nth loop"),
+            (
+                "local [name[=value]]",
+                "declare variables local to a function",
+            ),
+            ("return [n]", "return from a function or sourced script"),
+            ("source [file] [args]", "run commands from a file, also ."),
+            ("shift [n]", "drop the first n positional parameters"),
+            (
+                "export [-n|-p] [name[=value]]",
+                "pass variables on to commands, or list them",
+            ),
+            ("unset [-f] [name]", "remove variables or functions"),
+            ("functions [name]", "list function definitions"),
         ];
 
         let max_width = help_info.iter().map(|info| info.0.len()).max().unwrap();
@@ -151,37 +125,52 @@ pub mod builtins {
         }
     }
 
-    pub fn cd(args: &[String]) {
-        match args {
+    /// Changes the working directory, returning the previous one, if it
+    /// still exists, or the status to fail with.
+    pub fn cd(args: &[String], home: Option<&str>) -> Result<Option<String>, i32> {
+        let old = env::current_dir()
+            .ok()
+            .map(|dir| dir.to_string_lossy().into_owned());
+
+        let result = match args {
             [_] => {
-                let home = env::var("HOME").expect("error: $HOME not set");
-                if let Err(e) = env::set_current_dir(home) {
-                    println!("cd: operation failed: {}", e);
-                }
+                let Some(home) = home else {
+                    eprintln!("cd: HOME not set");
+                    return Err(1);
+                };
+                env::set_current_dir(home)
             }
-            [_, directory] => {
-                if let Err(e) = env::set_current_dir(directory) {
-                    println!("cd: operation failed: {}", e);
-                }
+            [_, directory] => env::set_current_dir(directory),
+            _ => {
+                eprintln!("cd: too many arguments");
+                return Err(1);
             }
-            _ => println!("cd: too many arguments"),
         };
+
+        match result {
+            Ok(()) => Ok(old),
+            Err(e) => {
+                eprintln!("cd: operation failed: {}", e);
+                Err(1)
+            }
+        }
     }
 
-    pub fn pwd(args: &[String]) {
+    pub fn pwd(args: &[String]) -> i32 {
         if args.len() > 1 {
-            println!("pwd: too many arguments");
-            return;
+            eprintln!("pwd: too many arguments");
+            return 1;
         }
 
         let curr_dir = match env::current_dir() {
             Ok(dir) => dir,
             Err(e) => {
-                println!("pwd: {}", e);
-                return;
+                eprintln!("pwd: {}", e);
+                return 1;
             }
         };
 
         println!("{}", curr_dir.display());
+        0
     }
 }
@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+/// A step debugger for scripts, enabled with `wsh --debug`. It stops before
+/// commands as asked and the shell reads what to do next.
+#[derive(Debug)]
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: BTreeSet<usize>,
+    /// The last `step`, `next` or `continue`, which an empty line repeats.
+    last: Option<DebugCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Stop before the next command.
+    Step,
+    /// Stop before the next command not in a function called from the one
+    /// at this depth.
+    Next(usize),
+    /// Stop only at breakpoints.
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// Runs the command, stopping before the next one, even inside a
+    /// function it calls.
+    Step,
+    /// Runs the command, stepping over the functions it calls.
+    Next,
+    /// Runs until a breakpoint.
+    Continue,
+    /// Expands the text as if in double quotes and prints it.
+    Print(String),
+    /// Sets a breakpoint on a line, or lists them.
+    Break(Option<usize>),
+    /// Removes the breakpoint on a line.
+    Delete(usize),
+    Help,
+    /// Leaves the shell.
+    Quit,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// Starts out stopping before the first command.
+    pub fn new() -> Debugger {
+        Debugger {
+            mode: Mode::Step,
+            breakpoints: BTreeSet::new(),
+            last: None,
+        }
+    }
+
+    /// Whether to stop before a command on `line`, run by functions nested
+    /// `depth` deep.
+    pub fn stops_at(&self, line: usize, depth: usize) -> bool {
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(from) => depth <= from || self.breakpoints.contains(&line),
+            Mode::Continue => self.breakpoints.contains(&line),
+        }
+    }
+
+    /// Parses a line read at a stop. An empty line repeats the last
+    /// `step`, `next` or `continue`.
+    pub fn parse(&mut self, input: &str) -> Result<DebugCommand, String> {
+        let input = input.trim();
+        let (name, arg) = input.split_once(' ').unwrap_or((input, ""));
+        let arg = arg.trim();
+        let line = |arg: &str| {
+            arg.parse::<usize>()
+                .map_err(|_| format!("{name}: {arg}: not a line number"))
+        };
+
+        let command = match name {
+            "" => {
+                return self
+                    .last
+                    .clone()
+                    .ok_or_else(|| "no command to repeat".to_owned());
+            }
+            "s" | "step" => DebugCommand::Step,
+            "n" | "next" => DebugCommand::Next,
+            "c" | "continue" => DebugCommand::Continue,
+            "p" | "print" => DebugCommand::Print(arg.to_owned()),
+            "b" | "break" if arg.is_empty() => DebugCommand::Break(None),
+            "b" | "break" => DebugCommand::Break(Some(line(arg)?)),
+            "d" | "delete" => DebugCommand::Delete(line(arg)?),
+            "h" | "help" => DebugCommand::Help,
+            "q" | "quit" => DebugCommand::Quit,
+            _ => return Err(format!("{name}: unknown command, try `help`")),
+        };
+        if matches!(
+            command,
+            DebugCommand::Step | DebugCommand::Next | DebugCommand::Continue
+        ) {
+            self.last = Some(command.clone());
+        }
+        Ok(command)
+    }
+
+    /// Lets the script run on after a stop at function `depth` until the
+    /// next stop `command` asks for.
+    pub fn resume(&mut self, command: &DebugCommand, depth: usize) {
+        self.mode = match command {
+            DebugCommand::Step => Mode::Step,
+            DebugCommand::Next => Mode::Next(depth),
+            DebugCommand::Continue => Mode::Continue,
+            _ => return,
+        };
+    }
+
+    /// Adds a breakpoint, returning whether it is new.
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.insert(line)
+    }
+
+    /// Removes a breakpoint, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn help() {
+        let help_info = [
+            ("s, step", "run the command, stopping inside functions"),
+            ("n, next", "run the command, stepping over functions"),
+            ("c, continue", "run until a breakpoint"),
+            ("p, print [text]", "expand text like \"text\" and print it"),
+            ("b, break [line]", "stop before commands on a line, or list"),
+            ("d, delete [line]", "remove a breakpoint"),
+            ("q, quit", "leave the shell"),
+        ];
+
+        let max_width = help_info.iter().map(|info| info.0.len()).max().unwrap();
+
+        for info in help_info {
+            eprintln!("{:>width$} - {}", info.0, info.1, width = max_width + 2);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use rustyline::{
+    Editor, Helper, Result,
+    completion::Completer,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+};
+
+use crate::parser;
+
+pub type LineReader = Editor<InputValidator, DefaultHistory>;
+
+pub fn line_reader() -> Result<LineReader> {
+    let mut reader = LineReader::new()?;
+    reader.set_helper(Some(InputValidator { enabled: true }));
+    Ok(reader)
+}
+
+/// Keeps Enter from running input that is not complete yet, such as an
+/// open quote, a trailing `|` or an `if` without its `fi`. The line is
+/// continued instead.
+pub struct InputValidator {
+    /// Cleared while reading text that is not a command, like a wish.
+    pub enabled: bool,
+}
+
+impl Validator for InputValidator {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        Ok(match self.enabled && parser::is_incomplete(ctx.input()) {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Completer for InputValidator {
+    type Candidate = String;
+}
+
+impl Hinter for InputValidator {
+    type Hint = String;
+}
+
+impl Highlighter for InputValidator {}
+
+impl Helper for InputValidator {}
@@ -0,0 +1,676 @@
+use nix::unistd::User;
+use thiserror::Error;
+
+use crate::{
+    arith::{self, ArithError},
+    brace,
+    glob::{self, GlobOptions},
+    param::{self, ParamExpansion, ParamOp},
+    scanner::{Scanner, Word, WordPart, substitution_len},
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ExpandError {
+    #[error("no match: {0}")]
+    NoMatch(String),
+    #[error("ambiguous redirect: {0}")]
+    AmbiguousRedirect(String),
+    #[error("bad substitution: {0}")]
+    BadSubstitution(String),
+    #[error("{0}: cannot assign in this way")]
+    BadAssignment(String),
+    #[error("{0}: {1}")]
+    Parameter(String, String),
+    #[error("{0}: unbound variable")]
+    Unbound(String),
+    #[error("arithmetic error: {0}")]
+    Arithmetic(#[from] ArithError),
+}
+
+impl ExpandError {
+    /// Whether the error ends a shell that is not interactive, as
+    /// `${var?message}`, a bad `$((...))` and using a variable that is not
+    /// set with `set -u` do.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ExpandError::Parameter(..) | ExpandError::Unbound(_) | ExpandError::Arithmetic(_)
+        )
+    }
+}
+
+/// What word expansion needs from the shell.
+pub trait Environment: arith::Variables {
+    fn set_var(&mut self, name: &str, value: String);
+    /// Runs `command` and returns its output without trailing newlines.
+    fn command_substitution(&mut self, command: &str) -> String;
+    fn glob_options(&self) -> GlobOptions;
+    /// The separate values of `name` inside double quotes, as for `"$@"`,
+    /// or `None` if it has a single value.
+    fn fields(&self, name: &str) -> Option<Vec<String>>;
+}
+
+/// Expanded text, remembering whether it was quoted and whether it is still
+/// subject to field splitting.
+#[derive(Debug)]
+struct Piece {
+    text: String,
+    quoted: bool,
+    split: bool,
+    /// Ends the field, as between the parameters of `"$@"`.
+    ends_field: bool,
+}
+
+/// Runs the full expansion pipeline on `word`: brace, tilde, parameter,
+/// command substitution and arithmetic expansion, then field splitting on
+/// `IFS`, globbing and quote removal. A word may expand to several fields,
+/// or to none.
+pub fn expand_word(word: &Word, env: &mut dyn Environment) -> Result<Vec<String>, ExpandError> {
+    let ifs = env.get("IFS").unwrap_or_else(|| DEFAULT_IFS.to_owned());
+    let options = env.glob_options();
+    let mut fields = vec![];
+
+    for word in brace::expand(word) {
+        let word = expand_tilde(word, false, &|name| env.get(name));
+        let pieces = expand_parts(&word.parts, false, env)?;
+        for field in split_fields(pieces, &ifs) {
+            fields.extend(expand_glob(field, &options)?.iter().map(Word::text));
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Expands every word of a command line.
+pub fn expand_words(words: &[Word], env: &mut dyn Environment) -> Result<Vec<String>, ExpandError> {
+    let mut fields = vec![];
+    for word in words {
+        fields.extend(expand_word(word, env)?);
+    }
+    Ok(fields)
+}
+
+/// Expands the target of a redirection, which must be exactly one field.
+pub fn expand_redirect(word: &Word, env: &mut dyn Environment) -> Result<String, ExpandError> {
+    let mut fields = expand_word(word, env)?;
+    match fields.len() {
+        1 => Ok(fields.remove(0)),
+        _ => Err(ExpandError::AmbiguousRedirect(word.text())),
+    }
+}
+
+/// Expands a `name=value` word into its name and value. The value is neither
+/// split nor globbed.
+pub fn expand_assignment(
+    word: &Word,
+    env: &mut dyn Environment,
+) -> Result<(String, String), ExpandError> {
+    let word = expand_tilde(word.clone(), true, &|name| env.get(name));
+    let text = join(expand_parts(&word.parts, false, env)?);
+    match text.split_once('=') {
+        Some((name, value)) => Ok((name.to_owned(), value.to_owned())),
+        None => Err(ExpandError::BadAssignment(text)),
+    }
+}
+
+/// Expands a here-document body or here-string into a single string, without
+/// field splitting or globbing.
+pub fn expand_content(word: &Word, env: &mut dyn Environment) -> Result<String, ExpandError> {
+    Ok(join(expand_parts(&word.parts, false, env)?))
+}
+
+/// Expands a `case` pattern without field splitting or globbing. Quoted
+/// characters in it only match themselves.
+pub fn expand_pattern(word: &Word, env: &mut dyn Environment) -> Result<String, ExpandError> {
+    let word = expand_tilde(word.clone(), false, &|name| env.get(name));
+    Ok(pattern(expand_parts(&word.parts, false, env)?))
+}
+
+/// Expands `text` as if it were inside double quotes, as for the
+/// expression of a `((...))` command.
+pub fn expand_string(text: &str, env: &mut dyn Environment) -> Result<String, ExpandError> {
+    let mut pieces = vec![];
+    expand_text(text, true, false, env, &mut pieces)?;
+    Ok(join(pieces))
+}
+
+const DEFAULT_IFS: &str = " \t\n";
+
+fn join(pieces: Vec<Piece>) -> String {
+    pieces.into_iter().map(|piece| piece.text).collect()
+}
+
+/// Expands the parts of a word. With `split_literals`, unquoted literal text
+/// is split as well, which is how the operand of `${v:-word}` behaves.
+fn expand_parts(
+    parts: &[WordPart],
+    split_literals: bool,
+    env: &mut dyn Environment,
+) -> Result<Vec<Piece>, ExpandError> {
+    let mut pieces = vec![];
+
+    for part in parts {
+        match part {
+            WordPart::Unquoted(text) => expand_text(text, false, split_literals, env, &mut pieces)?,
+            // "$@" without positional parameters makes no field at all
+            WordPart::DoubleQuoted(text) if text == "$@" || text == "${@}" => {
+                expand_text(text, true, false, env, &mut pieces)?;
+            }
+            WordPart::DoubleQuoted(text) => {
+                // even an empty "" makes a field
+                pieces.push(quoted(String::new()));
+                expand_text(text, true, false, env, &mut pieces)?;
+            }
+            WordPart::SingleQuoted(text) => pieces.push(quoted(text.clone())),
+            WordPart::Escaped(c) => pieces.push(quoted(c.to_string())),
+        }
+    }
+
+    Ok(pieces)
+}
+
+fn quoted(text: String) -> Piece {
+    Piece {
+        text,
+        quoted: true,
+        split: false,
+        ends_field: false,
+    }
+}
+
+/// Expands the `$` and `` ` `` substitutions in `text`. Their results are
+/// split later unless `quoted`.
+fn expand_text(
+    text: &str,
+    quoted: bool,
+    split_literals: bool,
+    env: &mut dyn Environment,
+    pieces: &mut Vec<Piece>,
+) -> Result<(), ExpandError> {
+    let mut literal = String::new();
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let (value, len) = match c {
+            '`' => {
+                let len = substitution_len(rest)
+                    .ok_or_else(|| ExpandError::BadSubstitution(rest.to_owned()))?;
+                let body = unescape_backquoted(&rest[1..len - 1]);
+                (env.command_substitution(&body), len)
+            }
+            '$' if rest.starts_with("$(") => {
+                let len = substitution_len(rest)
+                    .ok_or_else(|| ExpandError::BadSubstitution(rest.to_owned()))?;
+                let body = &rest[2..len - 1];
+                let value = match body.strip_prefix('(').and_then(|b| b.strip_suffix(')')) {
+                    Some(expr) => arith_value(expr, env)?.to_string(),
+                    None => env.command_substitution(body),
+                };
+                (value, len)
+            }
+            '$' if rest.starts_with("${") => {
+                let len = substitution_len(rest)
+                    .ok_or_else(|| ExpandError::BadSubstitution(rest.to_owned()))?;
+                if !literal.is_empty() {
+                    pieces.push(literal_piece(&mut literal, quoted, split_literals));
+                }
+                expand_param(&rest[2..len - 1], quoted, env, pieces)?;
+                i += len;
+                continue;
+            }
+            '$' => {
+                let name_len = match rest[1..].chars().next() {
+                    Some(c) if c.is_ascii_alphabetic() || c == '_' => rest[1..]
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len() - 1),
+                    Some(c) if param::SPECIAL_PARAMS.contains(c) => 1,
+                    // a lone `$` is just a dollar sign
+                    _ => 0,
+                };
+                if name_len == 0 {
+                    literal.push('$');
+                    i += 1;
+                    continue;
+                }
+                let name = &rest[1..1 + name_len];
+                if quoted && let Some(fields) = env.fields(name) {
+                    if !literal.is_empty() {
+                        pieces.push(literal_piece(&mut literal, quoted, split_literals));
+                    }
+                    push_fields(fields, pieces);
+                    i += 1 + name_len;
+                    continue;
+                }
+                let value = match env.get(name) {
+                    Some(value) => value,
+                    None if env.nounset() && !matches!(name, "@" | "*") => {
+                        return Err(ExpandError::Unbound(name.to_owned()));
+                    }
+                    None => String::new(),
+                };
+                (value, 1 + name_len)
+            }
+            c => {
+                literal.push(c);
+                i += c.len_utf8();
+                continue;
+            }
+        };
+
+        if !literal.is_empty() {
+            pieces.push(literal_piece(&mut literal, quoted, split_literals));
+        }
+        pieces.push(Piece {
+            text: value,
+            quoted,
+            split: !quoted,
+            ends_field: false,
+        });
+        i += len;
+    }
+
+    if !literal.is_empty() {
+        pieces.push(literal_piece(&mut literal, quoted, split_literals));
+    }
+
+    Ok(())
+}
+
+/// Adds each of `fields` as a field of its own, the first and last joining
+/// the text around them.
+fn push_fields(fields: Vec<String>, pieces: &mut Vec<Piece>) {
+    let last = fields.len().saturating_sub(1);
+    for (i, field) in fields.into_iter().enumerate() {
+        pieces.push(Piece {
+            ends_field: i != last,
+            ..quoted(field)
+        });
+    }
+}
+
+fn literal_piece(literal: &mut String, quoted: bool, split: bool) -> Piece {
+    Piece {
+        text: std::mem::take(literal),
+        quoted,
+        split: split && !quoted,
+        ends_field: false,
+    }
+}
+
+/// Removes the backslashes protecting `` ` ``, `\` and `$` in the body of a
+/// legacy `` `...` `` substitution.
+fn unescape_backquoted(body: &str) -> String {
+    let mut unescaped = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next @ ('`' | '\\' | '$')) if c == '\\' => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+fn arith_value(expr: &str, env: &mut dyn Environment) -> Result<i64, ExpandError> {
+    let expr = expand_string(expr, env)?;
+    Ok(arith::eval(&expr, env)?)
+}
+
+/// Expands the body of a `${...}` expansion into `pieces`.
+fn expand_param(
+    body: &str,
+    quoted: bool,
+    env: &mut dyn Environment,
+    pieces: &mut Vec<Piece>,
+) -> Result<(), ExpandError> {
+    let Some(ParamExpansion { name, op }) = param::parse(body) else {
+        return Err(ExpandError::BadSubstitution(format!("${{{body}}}")));
+    };
+
+    let value = env.get(&name);
+    let tests_set = matches!(
+        op,
+        ParamOp::Default { .. }
+            | ParamOp::Assign { .. }
+            | ParamOp::Alternate { .. }
+            | ParamOp::Error { .. }
+    );
+    if value.is_none() && !tests_set && env.nounset() && !matches!(&*name, "@" | "*") {
+        return Err(ExpandError::Unbound(name));
+    }
+    // whether the parameter counts as set, `:` forms also treat empty as unset
+    let is_set = |colon: bool| value.as_ref().is_some_and(|v| !colon || !v.is_empty());
+    let value_piece = |text: String| Piece {
+        text,
+        quoted,
+        split: !quoted,
+        ends_field: false,
+    };
+
+    let text = match op {
+        ParamOp::Plain if quoted && let Some(fields) = env.fields(&name) => {
+            push_fields(fields, pieces);
+            return Ok(());
+        }
+        ParamOp::Plain => value.unwrap_or_default(),
+        ParamOp::Length => value.unwrap_or_default().chars().count().to_string(),
+        ParamOp::Default { word, colon } => {
+            if is_set(colon) {
+                value.unwrap_or_default()
+            } else {
+                pieces.extend(operand(&word, quoted, env)?);
+                return Ok(());
+            }
+        }
+        ParamOp::Assign { word, colon } => {
+            if is_set(colon) {
+                value.unwrap_or_default()
+            } else {
+                if !is_name(&name) {
+                    return Err(ExpandError::BadAssignment(name));
+                }
+                let word = join(operand(&word, quoted, env)?);
+                env.set_var(&name, word.clone());
+                word
+            }
+        }
+        ParamOp::Alternate { word, colon } => {
+            if is_set(colon) {
+                pieces.extend(operand(&word, quoted, env)?);
+            }
+            return Ok(());
+        }
+        ParamOp::Error { word, colon } => {
+            if is_set(colon) {
+                value.unwrap_or_default()
+            } else {
+                let message = match join(operand(&word, quoted, env)?) {
+                    message if message.is_empty() => "parameter null or not set".to_owned(),
+                    message => message,
+                };
+                return Err(ExpandError::Parameter(name, message));
+            }
+        }
+        ParamOp::TrimPrefix { pattern, longest } => {
+            let pattern = pattern_operand(&pattern, env)?;
+            param::trim_prefix(&value.unwrap_or_default(), &pattern, longest)
+        }
+        ParamOp::TrimSuffix { pattern, longest } => {
+            let pattern = pattern_operand(&pattern, env)?;
+            param::trim_suffix(&value.unwrap_or_default(), &pattern, longest)
+        }
+        ParamOp::Substring { offset, length } => {
+            let offset = arith_value(&offset, env)?;
+            let length = match length {
+                Some(length) => Some(arith_value(&length, env)?),
+                None => None,
+            };
+            param::substring(&value.unwrap_or_default(), offset, length)
+        }
+        ParamOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        } => {
+            let pattern = pattern_operand(&pattern, env)?;
+            let replacement = join(operand(&replacement, quoted, env)?);
+            param::replace(&value.unwrap_or_default(), &pattern, &replacement, mode)
+        }
+        ParamOp::Case {
+            upper,
+            all,
+            pattern,
+        } => {
+            let pattern = pattern_operand(&pattern, env)?;
+            param::change_case(&value.unwrap_or_default(), upper, all, &pattern)
+        }
+    };
+
+    pieces.push(value_piece(text));
+    Ok(())
+}
+
+/// Expands the word operand of `${v:-word}` and friends. Inside double quotes
+/// the operand is treated as double-quoted text too.
+fn operand(text: &str, quoted: bool, env: &mut dyn Environment) -> Result<Vec<Piece>, ExpandError> {
+    if quoted {
+        let mut pieces = vec![];
+        expand_text(text, true, false, env, &mut pieces)?;
+        return Ok(pieces);
+    }
+
+    let word = Scanner::scan_whole_word(text)
+        .map_err(|_| ExpandError::BadSubstitution(text.to_owned()))?;
+    expand_parts(&word.parts, true, env)
+}
+
+/// Expands a pattern operand such as the `pat` in `${v#pat}`, escaping the
+/// parts that were quoted so they only match themselves.
+fn pattern_operand(text: &str, env: &mut dyn Environment) -> Result<String, ExpandError> {
+    let word = Scanner::scan_whole_word(text)
+        .map_err(|_| ExpandError::BadSubstitution(text.to_owned()))?;
+    Ok(pattern(expand_parts(&word.parts, false, env)?))
+}
+
+/// Joins pieces into a glob pattern in which quoted text only matches
+/// itself.
+fn pattern(pieces: Vec<Piece>) -> String {
+    let mut pattern = String::new();
+    for piece in pieces {
+        if piece.quoted {
+            pattern.push_str(&glob::escape(&piece.text));
+        } else {
+            pattern.push_str(&piece.text);
+        }
+    }
+    pattern
+}
+
+/// Splits the unquoted results of expansions on the characters of `ifs`.
+/// Runs of IFS whitespace separate fields and are trimmed at either end,
+/// while every other IFS character ends a field, even an empty one.
+/// Quoting is kept in the resulting words so globbing can respect it.
+fn split_fields(pieces: Vec<Piece>, ifs: &str) -> Vec<Word> {
+    let mut fields = vec![];
+    let mut parts = vec![];
+    // whether the current field exists, even if it is empty
+    let mut started = false;
+
+    for piece in pieces {
+        if !piece.split {
+            started |= piece.quoted || !piece.text.is_empty();
+            push_part(&mut parts, piece.text, piece.quoted);
+            if piece.ends_field {
+                fields.push(Word::new(std::mem::take(&mut parts)));
+                started = false;
+            }
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut chars = piece.text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                text.push(c);
+                started = true;
+                continue;
+            }
+
+            push_part(&mut parts, std::mem::take(&mut text), false);
+            let is_ifs_space = |c: &char| c.is_whitespace() && ifs.contains(*c);
+
+            let mut delimits = !c.is_whitespace();
+            while chars.next_if(is_ifs_space).is_some() {}
+            // whitespace around another IFS character belongs to it
+            if c.is_whitespace()
+                && chars
+                    .next_if(|c| ifs.contains(*c) && !c.is_whitespace())
+                    .is_some()
+            {
+                delimits = true;
+                while chars.next_if(is_ifs_space).is_some() {}
+            }
+
+            if started || delimits {
+                fields.push(Word::new(std::mem::take(&mut parts)));
+                started = false;
+            }
+        }
+        push_part(&mut parts, text, false);
+    }
+
+    if started {
+        fields.push(Word::new(parts));
+    }
+
+    fields
+}
+
+fn push_part(parts: &mut Vec<WordPart>, text: String, quoted: bool) {
+    if quoted {
+        parts.push(WordPart::SingleQuoted(text));
+    } else if !text.is_empty() {
+        parts.push(WordPart::Unquoted(text));
+    }
+}
+
+/// Whether `word` has the form `name=value` with an unquoted, valid name.
+pub fn is_assignment(word: &Word) -> bool {
+    let Some(WordPart::Unquoted(first)) = word.parts.first() else {
+        return false;
+    };
+
+    match first.split_once('=') {
+        Some((name, _)) => is_name(name),
+        None => false,
+    }
+}
+
+/// Whether `name` is a valid variable name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Expands an unquoted `~`, `~+`, `~-` or `~user` at the start of `word`. In
+/// assignments a tilde may also follow the `=` or any `:` in the value, as in
+/// `PATH=~/bin:~alice/bin`.
+pub fn expand_tilde(word: Word, assignment: bool, lookup: &dyn Fn(&str) -> Option<String>) -> Word {
+    let last = word.parts.len().saturating_sub(1);
+    let mut parts = Vec::with_capacity(word.parts.len());
+
+    for (i, part) in word.parts.into_iter().enumerate() {
+        let WordPart::Unquoted(text) = part else {
+            parts.push(part);
+            continue;
+        };
+
+        let mut starts = vec![];
+        if i == 0 {
+            starts.push(0);
+        }
+        if assignment {
+            let value_start = if i == 0 {
+                text.find('=').map_or(text.len(), |eq| eq + 1)
+            } else {
+                0
+            };
+            starts.push(value_start);
+            starts.extend(
+                text.match_indices(':')
+                    .map(|(j, _)| j + 1)
+                    .filter(|&j| j > value_start),
+            );
+        }
+
+        let mut pos = 0;
+        for start in starts {
+            if start < pos || !text[start..].starts_with('~') {
+                continue;
+            }
+
+            let end = text[start + 1..]
+                .find(|c| c == '/' || (assignment && c == ':'))
+                .map_or(text.len(), |e| start + 1 + e);
+            // the prefix must not continue into a quoted part
+            if end == text.len() && i != last {
+                continue;
+            }
+
+            if let Some(home) = tilde_home(&text[start + 1..end], lookup) {
+                if start > pos {
+                    parts.push(WordPart::Unquoted(text[pos..start].to_owned()));
+                }
+                parts.push(WordPart::SingleQuoted(home));
+                pos = end;
+            }
+        }
+
+        if pos < text.len() {
+            parts.push(WordPart::Unquoted(text[pos..].to_owned()));
+        }
+    }
+
+    Word::new(parts)
+}
+
+fn tilde_home(prefix: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    match prefix {
+        "" => lookup("HOME"),
+        "+" => lookup("PWD"),
+        "-" => lookup("OLDPWD"),
+        user => User::from_name(user)
+            .ok()
+            .flatten()
+            .map(|user| user.dir.to_string_lossy().into_owned()),
+    }
+}
+
+/// Expands unquoted wildcards in `word` against the filesystem. Quoted parts
+/// of the word only ever match themselves.
+pub fn expand_glob(word: Word, options: &GlobOptions) -> Result<Vec<Word>, ExpandError> {
+    let pattern = glob_pattern(&word);
+    if !glob::has_magic(&pattern) {
+        return Ok(vec![word]);
+    }
+
+    let paths = glob::expand(&pattern, options);
+    if !paths.is_empty() {
+        return Ok(paths
+            .into_iter()
+            .map(|path| Word::new(vec![WordPart::SingleQuoted(path)]))
+            .collect());
+    }
+
+    if options.failglob {
+        Err(ExpandError::NoMatch(word.text()))
+    } else if options.nullglob {
+        Ok(vec![])
+    } else {
+        Ok(vec![word])
+    }
+}
+
+/// Builds a glob pattern from `word` with its quoted parts escaped.
+fn glob_pattern(word: &Word) -> String {
+    let mut pattern = String::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Unquoted(s) => pattern.push_str(&s.replace('\\', "\\\\")),
+            WordPart::SingleQuoted(s) | WordPart::DoubleQuoted(s) => {
+                pattern.push_str(&glob::escape(s))
+            }
+            WordPart::Escaped(c) => pattern.push_str(&glob::escape(&c.to_string())),
+        }
+    }
+    pattern
+}
@@ -0,0 +1,364 @@
+use std::fs;
+
+/// Shell options controlling how patterns without matches are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobOptions {
+    /// Patterns that match nothing expand to nothing.
+    pub nullglob: bool,
+    /// Patterns that match nothing are an error.
+    pub failglob: bool,
+    /// Wildcards also match names starting with a `.`.
+    pub dotglob: bool,
+}
+
+impl GlobOptions {
+    pub fn list(&self) -> [(&'static str, bool); 3] {
+        [
+            ("dotglob", self.dotglob),
+            ("failglob", self.failglob),
+            ("nullglob", self.nullglob),
+        ]
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "failglob" => Some(&mut self.failglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PatternToken {
+    Char(char),
+    AnyChar,
+    AnyString,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+/// Characters with a special meaning inside a pattern.
+pub const MAGIC_CHARS: &str = "*?[]\\";
+
+/// Escapes `text` so that it only ever matches itself.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MAGIC_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the backslashes protecting characters in `pattern`.
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next().unwrap_or('\\')),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// Whether `pattern` contains an unescaped wildcard.
+pub fn has_magic(pattern: &str) -> bool {
+    compile(pattern)
+        .iter()
+        .any(|t| !matches!(t, PatternToken::Char(_)))
+}
+
+/// Matches `text` against a complete pattern. Unlike filename expansion,
+/// `/` and leading dots are not treated specially.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let tokens = compile(pattern);
+    let text: Vec<char> = text.chars().collect();
+    match_tokens(&tokens, &text)
+}
+
+/// Expands `pattern` against the filesystem, returning the sorted list of
+/// matching paths. `**` as a whole path component matches any number of
+/// directories.
+pub fn expand(pattern: &str, options: &GlobOptions) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    let mut prev_globstar = false;
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+
+        if component.is_empty() {
+            // a trailing slash only keeps directories, inner ones are redundant
+            if last {
+                paths.retain(|p| is_dir(p));
+                for path in &mut paths {
+                    if !path.ends_with('/') {
+                        path.push('/');
+                    }
+                }
+            }
+            continue;
+        }
+
+        if *component == "**" {
+            if prev_globstar {
+                continue;
+            }
+            prev_globstar = true;
+            paths = paths
+                .iter()
+                .flat_map(|p| {
+                    let mut found = if last { vec![] } else { vec![p.to_owned()] };
+                    walk(p, !last, options, &mut found);
+                    found
+                })
+                .collect();
+            continue;
+        }
+        prev_globstar = false;
+
+        if !has_magic(component) {
+            let name = unescape(component);
+            for path in &mut paths {
+                *path = join(path, &name);
+            }
+            continue;
+        }
+
+        let tokens = compile(component);
+        let match_dots = options.dotglob || component.starts_with('.');
+        paths = paths
+            .iter()
+            .flat_map(|p| {
+                let mut names: Vec<String> = read_dir(p)
+                    .into_iter()
+                    .filter(|name| {
+                        if name.starts_with('.') && !match_dots {
+                            return false;
+                        }
+                        let chars: Vec<char> = name.chars().collect();
+                        match_tokens(&tokens, &chars)
+                    })
+                    .collect();
+                names.sort();
+                names
+                    .into_iter()
+                    .map(|name| join(p, &name))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+    }
+
+    let mut paths: Vec<String> = paths
+        .into_iter()
+        .filter(|p| !p.is_empty() && fs::symlink_metadata(p).is_ok())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() || prefix.ends_with('/') {
+        format!("{prefix}{name}")
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    let path = if path.is_empty() { "." } else { path };
+    fs::metadata(path).is_ok_and(|m| m.is_dir())
+}
+
+fn read_dir(path: &str) -> Vec<String> {
+    let dir = if path.is_empty() { "." } else { path };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect()
+}
+
+/// Collects every entry below `path`, or only directories if `dirs_only`.
+/// Symbolic links are not followed to avoid cycles.
+fn walk(path: &str, dirs_only: bool, options: &GlobOptions, found: &mut Vec<String>) {
+    let mut names = read_dir(path);
+    names.sort();
+
+    for name in names {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+        let child = join(path, &name);
+        let is_dir = fs::symlink_metadata(&child).is_ok_and(|m| m.is_dir());
+        if is_dir || !dirs_only {
+            found.push(child.clone());
+        }
+        if is_dir {
+            walk(&child, dirs_only, options, found);
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Vec<PatternToken> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                i += 1;
+                tokens.push(PatternToken::Char(*chars.get(i).unwrap_or(&'\\')));
+            }
+            '*' => {
+                if tokens.last() != Some(&PatternToken::AnyString) {
+                    tokens.push(PatternToken::AnyString);
+                }
+            }
+            '?' => tokens.push(PatternToken::AnyChar),
+            '[' => match compile_class(&chars[i + 1..]) {
+                Some((token, len)) => {
+                    tokens.push(token);
+                    i += len;
+                }
+                // an unclosed bracket is an ordinary character
+                None => tokens.push(PatternToken::Char('[')),
+            },
+            c => tokens.push(PatternToken::Char(c)),
+        }
+        i += 1;
+    }
+
+    tokens
+}
+
+/// Compiles the body of a bracket expression, returning the token and the
+/// number of characters consumed including the closing `]`.
+fn compile_class(chars: &[char]) -> Option<(PatternToken, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = vec![];
+    let mut first = true;
+
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if !first => return Some((PatternToken::Class { negated, items }, i + 1)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest: String = chars[i + 2..].iter().collect();
+                match rest.find(":]") {
+                    Some(end) => {
+                        items.push(ClassItem::Named(rest[..end].to_owned()));
+                        i += 2 + rest[..end].chars().count() + 2;
+                    }
+                    None => {
+                        items.push(ClassItem::Char('['));
+                        i += 1;
+                    }
+                }
+            }
+            c => {
+                let c = if c == '\\' {
+                    i += 1;
+                    *chars.get(i)?
+                } else {
+                    c
+                };
+                if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&e| e != ']') {
+                    items.push(ClassItem::Range(c, chars[i + 2]));
+                    i += 3;
+                } else {
+                    items.push(ClassItem::Char(c));
+                    i += 1;
+                }
+            }
+        }
+        first = false;
+    }
+}
+
+fn class_matches(items: &[ClassItem], c: char) -> bool {
+    items.iter().any(|item| match item {
+        ClassItem::Char(x) => *x == c,
+        ClassItem::Range(lo, hi) => (*lo..=*hi).contains(&c),
+        ClassItem::Named(name) => match name.as_str() {
+            "alpha" => c.is_alphabetic(),
+            "digit" => c.is_ascii_digit(),
+            "alnum" => c.is_alphanumeric(),
+            "upper" => c.is_uppercase(),
+            "lower" => c.is_lowercase(),
+            "space" => c.is_whitespace(),
+            "blank" => c == ' ' || c == '\t',
+            "punct" => c.is_ascii_punctuation(),
+            "xdigit" => c.is_ascii_hexdigit(),
+            "cntrl" => c.is_control(),
+            "print" => !c.is_control(),
+            "graph" => !c.is_control() && !c.is_whitespace(),
+            _ => false,
+        },
+    })
+}
+
+fn match_tokens(tokens: &[PatternToken], text: &[char]) -> bool {
+    // iterative matching with backtracking to the most recent `*`
+    let (mut t, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let matched = match tokens.get(p) {
+            Some(PatternToken::AnyString) => {
+                star = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(PatternToken::AnyChar) => true,
+            Some(PatternToken::Char(c)) => *c == text[t],
+            Some(PatternToken::Class { negated, items }) => {
+                class_matches(items, text[t]) != *negated
+            }
+            None => false,
+        };
+
+        if matched {
+            t += 1;
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    tokens[p..]
+        .iter()
+        .all(|token| *token == PatternToken::AnyString)
+}
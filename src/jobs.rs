@@ -0,0 +1,337 @@
+use std::fmt;
+
+use nix::{
+    errno::Errno,
+    libc,
+    sys::wait::{WaitPidFlag, WaitStatus, waitpid},
+    unistd::Pid,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum JobError {
+    #[error("{0}: no such job")]
+    NoSuchJob(String),
+    #[error("{0}: ambiguous job spec")]
+    Ambiguous(String),
+    #[error("no current job")]
+    NoCurrentJob,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// Every process has finished. Holds the status of the last one.
+    Done(i32),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobState::Running => f.pad("Running"),
+            JobState::Stopped => f.pad("Stopped"),
+            JobState::Done(0) => f.pad("Done"),
+            JobState::Done(status) => f.pad(&format!("Exit {status}")),
+        }
+    }
+}
+
+/// A process of a job, with its exit status once it has been reaped.
+#[derive(Debug)]
+pub struct Process {
+    pub pid: Pid,
+    pub status: Option<i32>,
+}
+
+/// A pipeline, or a list run in the background, and its process group.
+#[derive(Debug)]
+pub struct Job {
+    /// The number used in `%n` specs, 0 until the job is added to a table.
+    pub id: usize,
+    pub pgid: Pid,
+    pub processes: Vec<Process>,
+    pub command: String,
+    pub state: JobState,
+    /// Set when the state changes, until the change has been reported.
+    pub changed: bool,
+}
+
+impl Job {
+    pub fn new(pgid: Pid, pids: Vec<Pid>, command: String) -> Job {
+        Job {
+            id: 0,
+            pgid,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process { pid, status: None })
+                .collect(),
+            command,
+            state: JobState::Running,
+            changed: false,
+        }
+    }
+
+    /// Records a status reported by `waitpid` for one of the processes.
+    pub fn update(&mut self, status: WaitStatus) {
+        let state = self.state;
+        match status {
+            WaitStatus::Exited(pid, code) => self.finish(pid, code),
+            WaitStatus::Signaled(pid, signal, _) => self.finish(pid, 128 + signal as i32),
+            WaitStatus::Stopped(..) => self.state = JobState::Stopped,
+            WaitStatus::Continued(_) => self.state = JobState::Running,
+            _ => (),
+        }
+        self.changed |= self.state != state;
+    }
+
+    fn finish(&mut self, pid: Pid, status: i32) {
+        if let Some(process) = self.processes.iter_mut().find(|p| p.pid == pid) {
+            process.status = Some(status);
+        }
+        if self.processes.iter().all(|p| p.status.is_some()) {
+            let last = self.processes.last().and_then(|p| p.status);
+            self.state = JobState::Done(last.unwrap_or(0));
+        }
+    }
+
+    /// Blocks until every process has finished or, if `untraced`, until the
+    /// job is stopped.
+    pub fn wait(&mut self, untraced: bool) {
+        let flags = untraced.then_some(WaitPidFlag::WUNTRACED);
+
+        while self.state == JobState::Running
+            && let Some(&pid) = self.pending().first()
+        {
+            match waitpid(pid, flags) {
+                Ok(status) => self.update(status),
+                Err(Errno::EINTR) => (),
+                // someone else reaped it
+                Err(_) => self.update(WaitStatus::Exited(pid, 1)),
+            }
+        }
+
+        // the other processes of a stopped pipeline report stopping as well,
+        // which should not be mistaken for a later stop
+        if self.state == JobState::Stopped {
+            self.poll();
+        }
+    }
+
+    /// Collects status changes of the processes without blocking.
+    pub fn poll(&mut self) {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+
+        for pid in self.pending() {
+            match waitpid(pid, Some(flags)) {
+                Ok(WaitStatus::StillAlive) | Err(Errno::EINTR) => (),
+                Ok(status) => self.update(status),
+                Err(_) => self.update(WaitStatus::Exited(pid, 1)),
+            }
+        }
+    }
+
+    /// The processes that have not been reaped yet.
+    pub fn pending(&self) -> Vec<Pid> {
+        self.processes
+            .iter()
+            .filter(|p| p.status.is_none())
+            .map(|p| p.pid)
+            .collect()
+    }
+
+    /// The command as shown by `jobs`, with a `&` while it runs.
+    pub fn display_command(&self) -> String {
+        match self.state {
+            JobState::Running => format!("{} &", self.command),
+            _ => self.command.clone(),
+        }
+    }
+}
+
+/// The jobs started by the shell that are running, stopped, or finished
+/// without having been reported yet.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    /// Job ids from least to most recently started or stopped. The last one
+    /// is the current job (`%+`), the one before it the previous (`%-`).
+    order: Vec<usize>,
+}
+
+impl JobTable {
+    /// Adds a job numbered one past the highest id and makes it the current
+    /// job.
+    pub fn add(&mut self, mut job: Job) -> usize {
+        let id = self.jobs.last().map_or(1, |last| last.id + 1);
+        job.id = id;
+        self.jobs.push(job);
+        self.make_current(id);
+        id
+    }
+
+    /// Puts back a job taken out with [`JobTable::remove`], keeping its id.
+    pub fn insert(&mut self, job: Job) {
+        let id = job.id;
+        let index = self.jobs.partition_point(|j| j.id < id);
+        self.jobs.insert(index, job);
+        self.make_current(id);
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.order.retain(|&i| i != id);
+        let index = self.jobs.iter().position(|j| j.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn make_current(&mut self, id: usize) {
+        self.order.retain(|&i| i != id);
+        self.order.push(id);
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Job> {
+        self.jobs.iter_mut()
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.iter().map(|j| j.id).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.order.last().copied()
+    }
+
+    pub fn previous(&self) -> Option<usize> {
+        self.order.iter().rev().nth(1).copied()
+    }
+
+    /// The job a process belongs to.
+    pub fn find_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|j| j.processes.iter().any(|p| p.pid == pid))
+            .map(|j| j.id)
+    }
+
+    /// Resolves a job spec: `%n` for job n, `%+` or `%%` for the current
+    /// job, `%-` for the previous one, `%string` for the job whose command
+    /// starts with string and `%?string` for the one containing it. The `%`
+    /// may be left out.
+    pub fn find(&self, spec: &str) -> Result<usize, JobError> {
+        let no_such_job = || JobError::NoSuchJob(spec.to_owned());
+
+        match spec.strip_prefix('%').unwrap_or(spec) {
+            "" | "+" | "%" => self.current().ok_or(JobError::NoCurrentJob),
+            "-" => self.previous().ok_or_else(no_such_job),
+            n if n.bytes().all(|b| b.is_ascii_digit()) => n
+                .parse()
+                .ok()
+                .filter(|&id| self.get(id).is_some())
+                .ok_or_else(no_such_job),
+            name => {
+                let matches: Vec<_> = match name.strip_prefix('?') {
+                    Some(text) => self
+                        .jobs
+                        .iter()
+                        .filter(|j| j.command.contains(text))
+                        .collect(),
+                    None => self
+                        .jobs
+                        .iter()
+                        .filter(|j| j.command.starts_with(name))
+                        .collect(),
+                };
+                match matches[..] {
+                    [job] => Ok(job.id),
+                    [] => Err(no_such_job()),
+                    _ => Err(JobError::Ambiguous(spec.to_owned())),
+                }
+            }
+        }
+    }
+
+    /// Formats a job the way `jobs` lists it, e.g. `[1]+  Running  cmd &`.
+    pub fn format(&self, job: &Job) -> String {
+        format!(
+            "[{}]{}  {:<24}{}",
+            job.id,
+            self.mark(job.id),
+            job.state,
+            job.display_command()
+        )
+    }
+
+    /// Formats a job the way `jobs -l` lists it, with its process group.
+    pub fn format_long(&self, job: &Job) -> String {
+        format!(
+            "[{}]{} {} {:<24}{}",
+            job.id,
+            self.mark(job.id),
+            job.pgid,
+            job.state,
+            job.display_command()
+        )
+    }
+
+    /// `+` for the current job, `-` for the previous one.
+    fn mark(&self, id: usize) -> char {
+        if Some(id) == self.current() {
+            '+'
+        } else if Some(id) == self.previous() {
+            '-'
+        } else {
+            ' '
+        }
+    }
+}
+
+/// Signals an interactive shell ignores, so that it is not stopped by the
+/// terminal while it manages jobs.
+const JOB_SIGNALS: [libc::c_int; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+/// Makes the shell ignore the job control signals.
+pub fn ignore_signals() {
+    for signal in JOB_SIGNALS {
+        // SAFETY: ignoring a signal installs no handler.
+        unsafe {
+            libc::signal(signal, libc::SIG_IGN);
+        }
+    }
+}
+
+/// Restores the default job control signal handling. Only makes system
+/// calls, so it is safe to run between fork and exec.
+pub fn reset_signals() {
+    for signal in JOB_SIGNALS {
+        // SAFETY: restoring the default action installs no handler.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Hands the terminal to the process group `pgid`, or to the calling
+/// process's own group if it is 0. Safe to run between fork and exec.
+pub fn give_terminal(terminal: libc::c_int, pgid: libc::pid_t) {
+    // SAFETY: these only make system calls.
+    unsafe {
+        let pgid = if pgid == 0 { libc::getpid() } else { pgid };
+        libc::tcsetpgrp(terminal, pgid);
+    }
+}
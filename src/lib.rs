@@ -1,7 +1,19 @@
+pub mod arith;
+pub mod brace;
 pub mod commands;
 pub mod config;
+pub mod debug;
+pub mod editor;
+pub mod expand;
+pub mod glob;
+pub mod jobs;
+pub mod options;
+pub mod param;
+pub mod parser;
+pub mod redirect;
 pub mod scanner;
 pub mod shell;
+pub mod vars;
 pub mod wish;
 
 pub use config::Config;
@@ -1,8 +1,15 @@
-use std::process;
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+    process,
+};
 
 use wsh::{Config, Shell};
 
-#[tokio::main]
+// the shell forks for command substitution, which is only safe while the
+// process has a single thread
+#[tokio::main(flavor = "current_thread")]
 async fn main() {
     let _ = dotenv::dotenv();
 
@@ -10,8 +17,41 @@ async fn main() {
 
     let mut shell = Shell::new(config);
 
-    if let Err(e) = shell.run().await {
-        eprintln!("application error: {}", e);
-        process::exit(1);
+    // `wsh`, `wsh -c command [name [arg...]]` or `wsh script [arg...]`,
+    // each optionally after `--debug`
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if_eq("--debug").is_some() {
+        shell.enable_debugger();
     }
+    let status = match args.next() {
+        None => match shell.run().await {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("application error: {}", e);
+                1
+            }
+        },
+        Some(flag) if flag == "-c" => {
+            let Some(command) = args.next() else {
+                eprintln!("wsh: -c: option requires an argument");
+                process::exit(2);
+            };
+            let name = args.next().unwrap_or_else(|| "wsh".to_owned());
+            shell.set_args(name, args.collect());
+            shell.run_string(&command)
+        }
+        Some(script) => {
+            shell.set_args(script.clone(), args.collect());
+            match shell.run_file(Path::new(&script)) {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("wsh: {script}: {e}");
+                    127
+                }
+            }
+        }
+    };
+
+    let _ = io::stdout().flush();
+    process::exit(status);
 }
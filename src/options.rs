@@ -0,0 +1,71 @@
+/// Shell options, set with `set -e` or `set -o errexit` and listed with
+/// `set -o`. Each has a name and most also a letter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// `-e`: the shell exits when a command fails, unless its status is
+    /// being tested.
+    pub errexit: bool,
+    /// `-C`: `>` does not overwrite existing files.
+    pub noclobber: bool,
+    /// `-u`: expanding a variable that is not set is an error.
+    pub nounset: bool,
+    /// A pipeline fails with the last stage that failed, not only when the
+    /// last stage does.
+    pub pipefail: bool,
+    /// `-x`: commands are printed before they run.
+    pub xtrace: bool,
+}
+
+/// The options with their letters, in the order they are listed.
+const NAMES: [(&str, Option<char>); 5] = [
+    ("errexit", Some('e')),
+    ("noclobber", Some('C')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+impl Options {
+    pub fn list(&self) -> [(&'static str, bool); 5] {
+        NAMES.map(|(name, _)| (name, self.get(name).unwrap_or_default()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "errexit" => Some(self.errexit),
+            "noclobber" => Some(self.noclobber),
+            "nounset" => Some(self.nounset),
+            "pipefail" => Some(self.pipefail),
+            "xtrace" => Some(self.xtrace),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "noclobber" => Some(&mut self.noclobber),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// The name of the option `letter` stands for, as in `set -e`.
+    pub fn name(letter: char) -> Option<&'static str> {
+        NAMES
+            .iter()
+            .find(|(_, l)| *l == Some(letter))
+            .map(|(name, _)| *name)
+    }
+
+    /// The letters of the options that are on, for `$-`.
+    pub fn flags(&self) -> String {
+        NAMES
+            .iter()
+            .filter(|(name, _)| self.get(name) == Some(true))
+            .filter_map(|(_, letter)| *letter)
+            .collect()
+    }
+}
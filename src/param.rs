@@ -0,0 +1,344 @@
+use crate::glob;
+
+/// A parsed `${...}` parameter expansion. Operand words are kept unexpanded.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParamExpansion {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+/// Which end of the value a pattern is anchored to in `${v/pat/rep}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceMode {
+    First,
+    All,
+    Prefix,
+    Suffix,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParamOp {
+    /// `${v}`
+    Plain,
+    /// `${#v}`
+    Length,
+    /// `${v:-word}`, or `${v-word}` without the colon
+    Default { word: String, colon: bool },
+    /// `${v:=word}`
+    Assign { word: String, colon: bool },
+    /// `${v:+word}`
+    Alternate { word: String, colon: bool },
+    /// `${v:?word}`
+    Error { word: String, colon: bool },
+    /// `${v#pat}` and `${v##pat}`
+    TrimPrefix { pattern: String, longest: bool },
+    /// `${v%pat}` and `${v%%pat}`
+    TrimSuffix { pattern: String, longest: bool },
+    /// `${v:offset}` and `${v:offset:length}`, both arithmetic expressions
+    Substring {
+        offset: String,
+        length: Option<String>,
+    },
+    /// `${v/pat/rep}`, `${v//pat/rep}`, `${v/#pat/rep}` and `${v/%pat/rep}`
+    Replace {
+        pattern: String,
+        replacement: String,
+        mode: ReplaceMode,
+    },
+    /// `${v^pat}`, `${v^^pat}`, `${v,pat}` and `${v,,pat}`
+    Case {
+        upper: bool,
+        all: bool,
+        pattern: String,
+    },
+}
+
+/// Characters that are parameter names on their own, e.g. `$?` or `$#`.
+pub const SPECIAL_PARAMS: &str = "?#$!@*-0123456789";
+
+/// Parses the body of a `${...}` expansion.
+pub fn parse(body: &str) -> Option<ParamExpansion> {
+    // `${#}` is the number of positional parameters, `${#v}` a length
+    if let Some(name) = body.strip_prefix('#')
+        && !name.is_empty()
+    {
+        let (name, rest) = split_name(name)?;
+        return rest.is_empty().then(|| ParamExpansion {
+            name: name.to_owned(),
+            op: ParamOp::Length,
+        });
+    }
+
+    let (name, rest) = split_name(body)?;
+    let word = |prefix: usize| rest[prefix..].to_owned();
+
+    let op = if rest.is_empty() {
+        ParamOp::Plain
+    } else if let Some(op) = colon_op(rest) {
+        op
+    } else if let Some(pattern) = rest.strip_prefix("##") {
+        ParamOp::TrimPrefix {
+            pattern: pattern.to_owned(),
+            longest: true,
+        }
+    } else if let Some(pattern) = rest.strip_prefix('#') {
+        ParamOp::TrimPrefix {
+            pattern: pattern.to_owned(),
+            longest: false,
+        }
+    } else if let Some(pattern) = rest.strip_prefix("%%") {
+        ParamOp::TrimSuffix {
+            pattern: pattern.to_owned(),
+            longest: true,
+        }
+    } else if let Some(pattern) = rest.strip_prefix('%') {
+        ParamOp::TrimSuffix {
+            pattern: pattern.to_owned(),
+            longest: false,
+        }
+    } else if let Some(rest) = rest.strip_prefix('/') {
+        let (mode, rest) = match rest.chars().next() {
+            Some('/') => (ReplaceMode::All, &rest[1..]),
+            Some('#') => (ReplaceMode::Prefix, &rest[1..]),
+            Some('%') => (ReplaceMode::Suffix, &rest[1..]),
+            _ => (ReplaceMode::First, rest),
+        };
+        let (pattern, replacement) = split_unescaped(rest, '/').unwrap_or((rest, ""));
+        ParamOp::Replace {
+            pattern: pattern.to_owned(),
+            replacement: replacement.to_owned(),
+            mode,
+        }
+    } else if rest.starts_with(['^', ',']) {
+        let upper = rest.starts_with('^');
+        let all = rest[1..].starts_with(&rest[..1]);
+        ParamOp::Case {
+            upper,
+            all,
+            pattern: word(if all { 2 } else { 1 }),
+        }
+    } else {
+        match &rest[..1] {
+            "-" => ParamOp::Default {
+                word: word(1),
+                colon: false,
+            },
+            "=" => ParamOp::Assign {
+                word: word(1),
+                colon: false,
+            },
+            "+" => ParamOp::Alternate {
+                word: word(1),
+                colon: false,
+            },
+            "?" => ParamOp::Error {
+                word: word(1),
+                colon: false,
+            },
+            _ => return None,
+        }
+    };
+
+    Some(ParamExpansion {
+        name: name.to_owned(),
+        op,
+    })
+}
+
+/// Splits a parameter name off the start of `body`.
+fn split_name(body: &str) -> Option<(&str, &str)> {
+    let first = body.chars().next()?;
+    let len = if first.is_ascii_alphabetic() || first == '_' {
+        let len = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(body.len());
+        // a subscript, as in `PIPESTATUS[1]` or `PIPESTATUS[@]`
+        match body[len..]
+            .strip_prefix('[')
+            .and_then(|rest| rest.find(']'))
+        {
+            Some(end) => len + end + 2,
+            None => len,
+        }
+    } else if first.is_ascii_digit() {
+        body.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(body.len())
+    } else if SPECIAL_PARAMS.contains(first) {
+        1
+    } else {
+        return None;
+    };
+
+    Some(body.split_at(len))
+}
+
+fn colon_op(rest: &str) -> Option<ParamOp> {
+    let after = rest.strip_prefix(':')?;
+    let word = after.get(1..).unwrap_or_default().to_owned();
+
+    Some(match after.chars().next() {
+        Some('-') => ParamOp::Default { word, colon: true },
+        Some('=') => ParamOp::Assign { word, colon: true },
+        Some('+') => ParamOp::Alternate { word, colon: true },
+        Some('?') => ParamOp::Error { word, colon: true },
+        _ => {
+            let (offset, length) = match split_unescaped(after, ':') {
+                Some((offset, length)) => (offset, Some(length.to_owned())),
+                None => (after, None),
+            };
+            ParamOp::Substring {
+                offset: offset.to_owned(),
+                length,
+            }
+        }
+    })
+}
+
+fn split_unescaped(text: &str, separator: char) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            return Some((&text[..i], &text[i + 1..]));
+        }
+    }
+    None
+}
+
+/// Byte offsets of every character boundary in `value`.
+fn boundaries(value: &str) -> Vec<usize> {
+    value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([value.len()])
+        .collect()
+}
+
+/// Removes the shortest (or longest) prefix of `value` matching `pattern`.
+pub fn trim_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+
+    ends.into_iter()
+        .find(|&end| glob::matches(pattern, &value[..end]))
+        .map_or(value, |end| &value[end..])
+        .to_owned()
+}
+
+/// Removes the shortest (or longest) suffix of `value` matching `pattern`.
+pub fn trim_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+
+    starts
+        .into_iter()
+        .find(|&start| glob::matches(pattern, &value[start..]))
+        .map_or(value, |start| &value[..start])
+        .to_owned()
+}
+
+/// Replaces the longest matches of `pattern` in `value`.
+pub fn replace(value: &str, pattern: &str, replacement: &str, mode: ReplaceMode) -> String {
+    if pattern.is_empty() {
+        return value.to_owned();
+    }
+
+    let bounds = boundaries(value);
+    let longest_match = |start: usize| {
+        bounds
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&end| end >= start)
+            .find(|&end| glob::matches(pattern, &value[start..end]))
+    };
+
+    match mode {
+        ReplaceMode::Prefix => match longest_match(0) {
+            Some(end) => format!("{replacement}{}", &value[end..]),
+            None => value.to_owned(),
+        },
+        ReplaceMode::Suffix => bounds
+            .iter()
+            .find(|&&start| glob::matches(pattern, &value[start..]))
+            .map_or(value.to_owned(), |&start| {
+                format!("{}{replacement}", &value[..start])
+            }),
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut result = String::new();
+            let mut copied = 0;
+            let mut i = 0;
+
+            while i < bounds.len() - 1 {
+                let start = bounds[i];
+                match longest_match(start) {
+                    // empty matches are skipped so the loop makes progress
+                    Some(end) if end > start => {
+                        result.push_str(&value[copied..start]);
+                        result.push_str(replacement);
+                        copied = end;
+                        if mode == ReplaceMode::First {
+                            break;
+                        }
+                        i = bounds.iter().position(|&b| b == end).unwrap();
+                    }
+                    _ => i += 1,
+                }
+            }
+
+            result.push_str(&value[copied..]);
+            result
+        }
+    }
+}
+
+/// Changes the case of the first (or every) character matching `pattern`,
+/// where an empty pattern matches any character.
+pub fn change_case(value: &str, upper: bool, all: bool, pattern: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+
+    for (i, c) in value.chars().enumerate() {
+        let matches = pattern.is_empty() || glob::matches(pattern, &c.to_string());
+        if (all || i == 0) && matches {
+            if upper {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Takes `length` characters starting at `offset`. Negative offsets count
+/// from the end of the value, as does a negative length.
+pub fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return String::new();
+    }
+
+    let end = match length {
+        Some(length) if length < 0 => len + length,
+        Some(length) => start.saturating_add(length).min(len),
+        None => len,
+    };
+    if end < start {
+        return String::new();
+    }
+
+    chars[start as usize..end as usize].iter().collect()
+}
@@ -0,0 +1,711 @@
+use std::{iter::Peekable, rc::Rc, slice};
+
+use thiserror::Error;
+
+use crate::{
+    expand,
+    scanner::{RedirectOp, ScanErrorKind, Scanner, Span, Token, TokenKind, Word},
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    #[error("syntax error near unexpected token `{0}`")]
+    Unexpected(String),
+    #[error("syntax error: unexpected end of input")]
+    UnexpectedEof,
+    #[error("syntax error: no target provided after redirection")]
+    MissingTarget,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseError {
+    /// Renders the offending line of `source` with a caret under the error.
+    pub fn render(&self, source: &str) -> String {
+        self.span.render(source, &self.kind.to_string())
+    }
+}
+
+/// A whole input, as given on a line or read from a script.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub body: List,
+}
+
+/// And-or lists separated by `;`, `&` or newlines.
+#[derive(Debug, Clone, Default)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Set if the list was terminated by `&`.
+    pub background: bool,
+    /// The source text, without the `&`.
+    pub text: String,
+}
+
+/// How an and-or list continues after a pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`, runs the next pipeline if this one succeeded
+    And,
+    /// `||`, runs the next pipeline if this one failed
+    Or,
+}
+
+/// Commands joined by `|`. With a leading `!` the exit status is negated.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    /// The source text, shown for jobs.
+    pub text: String,
+    /// The line it starts on, for `LINENO` and the debugger.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// A compound command, run in the shell itself unless it is a subshell
+    /// or part of a pipeline.
+    Compound {
+        compound: Compound,
+        redirects: Vec<Redirect>,
+    },
+    /// `name() compound-command`
+    FunctionDef {
+        name: String,
+        function: Rc<Function>,
+    },
+    /// `((expr))`, with the expression not yet expanded.
+    Arith(String),
+}
+
+/// Assignments, words and redirections, none of them expanded yet. Any of
+/// the three may be empty, but not all of them.
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    /// The leading `name=value` words.
+    pub assignments: Vec<Word>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A redirection as written, before its target is expanded. For
+/// here-documents the target is the body.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: i32,
+    pub op: RedirectOp,
+    pub target: Word,
+}
+
+/// A command made of lists of other commands.
+#[derive(Debug, Clone)]
+pub enum Compound {
+    /// `( list )`, run in a forked copy of the shell.
+    Subshell(List),
+    /// `{ list; }`
+    Group(List),
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`,
+    /// with the conditions and bodies in `branches`.
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while list; do list; done`, or with `until` set, `until`, which
+    /// loops for as long as the condition fails.
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    /// `for name [in word...]; do list; done`. Without `in` it loops over
+    /// the positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    /// `case word in [(]pattern[|pattern]...) list;; ... esac`
+    Case { word: Word, arms: Vec<CaseArm> },
+}
+
+/// The patterns of a `case` and the list run if one of them matches.
+#[derive(Debug, Clone)]
+pub struct CaseArm {
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+/// A shell function: its body, which is a compound command, and the source
+/// text of its definition.
+#[derive(Debug)]
+pub struct Function {
+    pub body: Command,
+    pub text: String,
+}
+
+/// Parses tokens scanned from `source` into a program.
+pub fn parse(tokens: &[Token], source: &str) -> Result<Program, ParseError> {
+    parse_at(tokens, source, 1)
+}
+
+/// Parses tokens scanned from `source`, which starts on line `first_line`
+/// of a script, into a program.
+pub fn parse_at(tokens: &[Token], source: &str, first_line: usize) -> Result<Program, ParseError> {
+    let mut parser = Parser {
+        tokens: tokens.iter().peekable(),
+        source,
+        end: 0,
+        first_line,
+    };
+    parser.program()
+}
+
+/// Whether `source` stops in the middle of a command, so that it needs more
+/// lines before it can run: inside quotes, a substitution or a
+/// here-document, after `|`, `&&` or `||`, or before the end of a compound
+/// command.
+pub fn is_incomplete(source: &str) -> bool {
+    match Scanner::new(source).scan_tokens() {
+        Ok(tokens) => matches!(
+            parse(&tokens, source),
+            Err(ParseError {
+                kind: ParseErrorKind::UnexpectedEof,
+                ..
+            })
+        ),
+        Err(e) => matches!(
+            e.kind,
+            ScanErrorKind::UnterminatedQuote(_)
+                | ScanErrorKind::UnterminatedSubstitution(_)
+                | ScanErrorKind::UnterminatedArithmetic
+                | ScanErrorKind::UnterminatedHereDoc(_)
+        ),
+    }
+}
+
+/// Words with a meaning of their own where a command may start.
+const RESERVED_WORDS: [&str; 17] = [
+    "!", "{", "}", "if", "then", "elif", "else", "fi", "while", "until", "do", "done", "for", "in",
+    "case", "esac", "function",
+];
+
+struct Parser<'a> {
+    tokens: Peekable<slice::Iter<'a, Token>>,
+    source: &'a str,
+    /// Where the last consumed token ended.
+    end: usize,
+    /// The line of the script `source` starts on.
+    first_line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let body = self.list(&[])?;
+        match self.peek() {
+            TokenKind::Eof => Ok(Program { body }),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Parses and-or lists separated by `;`, `&` or newlines, up to the end
+    /// of the input, a `)` or one of the reserved words in `end`. The caller
+    /// checks which one it was.
+    fn list(&mut self, end: &[&str]) -> Result<List, ParseError> {
+        let mut items = vec![];
+
+        loop {
+            self.skip_newlines();
+            if self.at_list_end(end) {
+                break;
+            }
+
+            items.push(self.and_or()?);
+
+            match self.peek() {
+                TokenKind::Semi | TokenKind::Newline => (),
+                TokenKind::Amp => items.last_mut().unwrap().background = true,
+                _ => break,
+            }
+            self.next();
+        }
+
+        Ok(List { items })
+    }
+
+    /// Parses a list that must contain at least one command.
+    fn body(&mut self, end: &[&str]) -> Result<List, ParseError> {
+        let list = self.list(end)?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn at_list_end(&mut self, end: &[&str]) -> bool {
+        match self.peek() {
+            TokenKind::Eof | TokenKind::RParen | TokenKind::DSemi => true,
+            TokenKind::Literal(word) => end.iter().any(|&reserved| *word == Word::from(reserved)),
+            _ => false,
+        }
+    }
+
+    /// Consumes the reserved word `reserved`, which must come next.
+    fn expect_reserved(&mut self, reserved: &str) -> Result<(), ParseError> {
+        match self.reserved() {
+            Some(word) if word == reserved => {
+                self.next();
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Consumes the token `kind`, which must come next.
+    fn expect(&mut self, kind: TokenKind) -> Result<(), ParseError> {
+        if *self.peek() != kind {
+            return Err(self.unexpected());
+        }
+        self.next();
+        Ok(())
+    }
+
+    /// The reserved word coming next, if any. Reserved words are only
+    /// recognized unquoted, and only where a command may start.
+    fn reserved(&mut self) -> Option<&'static str> {
+        match self.peek() {
+            TokenKind::Literal(word) => RESERVED_WORDS
+                .into_iter()
+                .find(|&reserved| *word == Word::from(reserved)),
+            _ => None,
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ParseError> {
+        let start = self.start();
+        let first = self.pipeline()?;
+        let mut rest = vec![];
+
+        loop {
+            let connector = match self.peek() {
+                TokenKind::AndIf => Connector::And,
+                TokenKind::OrIf => Connector::Or,
+                _ => break,
+            };
+            self.next();
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+
+        Ok(AndOr {
+            first,
+            rest,
+            background: false,
+            text: self.text(start),
+        })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let start = self.start();
+        let negated = matches!(self.peek(), TokenKind::Literal(word) if *word == Word::from("!"));
+        if negated {
+            self.next();
+        }
+
+        let mut commands = vec![self.command()?];
+        while self.peek() == &TokenKind::Pipe {
+            self.next();
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+
+        Ok(Pipeline {
+            negated,
+            commands,
+            text: self.text(start),
+            line: self.first_line + self.source[..start].matches('\n').count(),
+        })
+    }
+
+    /// Parses a single command, which may be a compound one.
+    fn command(&mut self) -> Result<Command, ParseError> {
+        if let TokenKind::Arith(expr) = self.peek() {
+            self.next();
+            return Ok(Command::Arith(expr.to_owned()));
+        }
+
+        let reserved = self.reserved();
+        if reserved == Some("function")
+            || reserved.is_none()
+                && matches!(self.peek(), TokenKind::Literal(_))
+                && self.peek_second() == &TokenKind::LParen
+        {
+            return self.function_definition();
+        }
+
+        let compound = match (self.peek(), reserved) {
+            (TokenKind::LParen, _) => {
+                self.next();
+                let body = self.body(&[])?;
+                self.expect(TokenKind::RParen)?;
+                Compound::Subshell(body)
+            }
+            (_, Some("{")) => {
+                self.next();
+                let body = self.body(&["}"])?;
+                self.expect_reserved("}")?;
+                Compound::Group(body)
+            }
+            (_, Some("if")) => self.if_clause()?,
+            (_, Some(keyword @ ("while" | "until"))) => {
+                self.next();
+                let condition = self.body(&["do"])?;
+                let body = self.do_group()?;
+                Compound::While {
+                    condition,
+                    body,
+                    until: keyword == "until",
+                }
+            }
+            (_, Some("for")) => self.for_clause()?,
+            (_, Some("case")) => self.case_clause()?,
+            // only `!` may start a command, and the pipeline has taken it
+            (_, Some(_)) => return Err(self.unexpected()),
+            _ => return self.simple_command(),
+        };
+
+        let redirects = self.redirects()?;
+        Ok(Command::Compound {
+            compound,
+            redirects,
+        })
+    }
+
+    /// Parses `name() compound-command` or `function name [()]
+    /// compound-command`.
+    fn function_definition(&mut self) -> Result<Command, ParseError> {
+        let start = self.start();
+        let keyword = self.reserved() == Some("function");
+        if keyword {
+            self.next();
+        }
+
+        let name = match self.peek() {
+            TokenKind::Literal(word) if !word.is_quoted() && !expand::is_assignment(word) => {
+                word.text()
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.next();
+        if self.peek() == &TokenKind::LParen || !keyword {
+            self.expect(TokenKind::LParen)?;
+            self.expect(TokenKind::RParen)?;
+        }
+        self.skip_newlines();
+
+        let compound = matches!(
+            self.reserved(),
+            Some("{" | "if" | "while" | "until" | "for" | "case")
+        );
+        if !compound && self.peek() != &TokenKind::LParen {
+            return Err(self.unexpected());
+        }
+        let body = self.command()?;
+
+        Ok(Command::FunctionDef {
+            name,
+            function: Rc::new(Function {
+                body,
+                text: self.text(start),
+            }),
+        })
+    }
+
+    /// Parses `if` up to its `fi`.
+    fn if_clause(&mut self) -> Result<Compound, ParseError> {
+        let mut branches = vec![];
+        let mut otherwise = None;
+
+        // `if` or `elif`
+        while let Some("if" | "elif") = self.reserved() {
+            self.next();
+            let condition = self.body(&["then"])?;
+            self.expect_reserved("then")?;
+            let body = self.body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+        }
+        if let Some("else") = self.reserved() {
+            self.next();
+            otherwise = Some(self.body(&["fi"])?);
+        }
+        self.expect_reserved("fi")?;
+
+        Ok(Compound::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    /// Parses `do list done`.
+    fn do_group(&mut self) -> Result<List, ParseError> {
+        self.expect_reserved("do")?;
+        let body = self.body(&["done"])?;
+        self.expect_reserved("done")?;
+        Ok(body)
+    }
+
+    /// Parses `for name [in word...]` and the loop body.
+    fn for_clause(&mut self) -> Result<Compound, ParseError> {
+        self.next();
+        let name = match self.peek() {
+            TokenKind::Literal(word) if !word.is_quoted() && expand::is_name(&word.text()) => {
+                word.text()
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.next();
+
+        self.skip_newlines();
+        let mut words = None;
+        if let Some("in") = self.reserved() {
+            self.next();
+            let mut list = vec![];
+            while let TokenKind::Literal(word) = self.peek() {
+                list.push(word.clone());
+                self.next();
+            }
+            words = Some(list);
+        }
+        match self.peek() {
+            TokenKind::Semi | TokenKind::Newline => {
+                self.next();
+            }
+            _ if words.is_none() => (),
+            _ => return Err(self.unexpected()),
+        }
+        self.skip_newlines();
+
+        let body = self.do_group()?;
+        Ok(Compound::For { name, words, body })
+    }
+
+    /// Parses `case word in` up to its `esac`.
+    fn case_clause(&mut self) -> Result<Compound, ParseError> {
+        self.next();
+        let TokenKind::Literal(word) = self.peek() else {
+            return Err(self.unexpected());
+        };
+        self.next();
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+
+        let mut arms = vec![];
+        loop {
+            self.skip_newlines();
+            if let Some("esac") = self.reserved() {
+                break;
+            }
+
+            if self.peek() == &TokenKind::LParen {
+                self.next();
+            }
+            let mut patterns = vec![];
+            loop {
+                let TokenKind::Literal(pattern) = self.peek() else {
+                    return Err(self.unexpected());
+                };
+                patterns.push(pattern.clone());
+                self.next();
+                match self.peek() {
+                    TokenKind::Pipe => self.next(),
+                    TokenKind::RParen => break,
+                    _ => return Err(self.unexpected()),
+                };
+            }
+            self.next();
+
+            let body = self.list(&["esac"])?;
+            arms.push(CaseArm { patterns, body });
+
+            match self.peek() {
+                TokenKind::DSemi => {
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+        self.expect_reserved("esac")?;
+
+        Ok(Compound::Case {
+            word: word.clone(),
+            arms,
+        })
+    }
+
+    /// Parses a simple command. Leading `name=value` words are its
+    /// assignments.
+    fn simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut cmd = SimpleCommand::default();
+
+        loop {
+            match self.peek() {
+                TokenKind::Literal(word) => {
+                    if cmd.words.is_empty() && expand::is_assignment(word) {
+                        cmd.assignments.push(word.clone());
+                    } else {
+                        cmd.words.push(word.clone());
+                    }
+                    self.next();
+                }
+                _ => match self.redirect()? {
+                    Some(redirect) => cmd.redirects.push(redirect),
+                    None => break,
+                },
+            }
+        }
+
+        if cmd.assignments.is_empty() && cmd.words.is_empty() && cmd.redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        Ok(Command::Simple(cmd))
+    }
+
+    /// Parses the redirections after a compound command.
+    fn redirects(&mut self) -> Result<Vec<Redirect>, ParseError> {
+        let mut redirects = vec![];
+        while let Some(redirect) = self.redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(redirects)
+    }
+
+    /// Parses a redirection, if one comes next.
+    fn redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let redirect = match self.peek() {
+            &TokenKind::Redirect { fd, op } => {
+                let span = self.span();
+                self.next();
+                let TokenKind::Literal(target) = self.peek() else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::MissingTarget,
+                        span,
+                    });
+                };
+                self.next();
+                Redirect {
+                    fd: fd.unwrap_or(op.default_fd()),
+                    op,
+                    target: target.clone(),
+                }
+            }
+            TokenKind::HereDoc { fd, body } => {
+                self.next();
+                Redirect {
+                    fd: fd.unwrap_or(0),
+                    op: RedirectOp::HereDoc,
+                    target: body.clone(),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(redirect))
+    }
+
+    /// An error for the token coming next.
+    fn unexpected(&mut self) -> ParseError {
+        let kind = match self.peek() {
+            TokenKind::Eof => ParseErrorKind::UnexpectedEof,
+            kind => ParseErrorKind::Unexpected(describe(kind)),
+        };
+        ParseError {
+            kind,
+            span: self.span(),
+        }
+    }
+
+    fn peek(&mut self) -> &'a TokenKind {
+        self.tokens
+            .peek()
+            .map_or(&TokenKind::Eof, |token| &token.kind)
+    }
+
+    /// The token after the next one.
+    fn peek_second(&self) -> &'a TokenKind {
+        self.tokens
+            .clone()
+            .nth(1)
+            .map_or(&TokenKind::Eof, |token| &token.kind)
+    }
+
+    /// Where the next token is, or the end of the input.
+    fn span(&mut self) -> Span {
+        let end = self.source.len();
+        self.tokens
+            .peek()
+            .map_or(Span::new(end, end), |token| token.span)
+    }
+
+    fn next(&mut self) -> &'a TokenKind {
+        match self.tokens.next() {
+            Some(token) => {
+                self.end = token.span.end;
+                &token.kind
+            }
+            None => &TokenKind::Eof,
+        }
+    }
+
+    /// Where the next token starts.
+    fn start(&mut self) -> usize {
+        self.tokens
+            .peek()
+            .map_or(self.end, |token| token.span.start)
+    }
+
+    /// The source text from `start` to the end of the last consumed token.
+    fn text(&self, start: usize) -> String {
+        self.source
+            .get(start..self.end)
+            .unwrap_or_default()
+            .to_owned()
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == &TokenKind::Newline {
+            self.next();
+        }
+    }
+}
+
+/// How a token is shown in an error.
+fn describe(kind: &TokenKind) -> String {
+    let token = match kind {
+        TokenKind::Pipe => "|",
+        TokenKind::AndIf => "&&",
+        TokenKind::OrIf => "||",
+        TokenKind::Semi => ";",
+        TokenKind::DSemi => ";;",
+        TokenKind::Amp => "&",
+        TokenKind::LParen => "(",
+        TokenKind::RParen => ")",
+        TokenKind::Newline | TokenKind::Eof => "newline",
+        TokenKind::Arith(_) => "((",
+        TokenKind::Redirect { .. } | TokenKind::HereDoc { .. } => "redirection",
+        TokenKind::Literal(word) => return word.text(),
+    };
+    token.to_owned()
+}
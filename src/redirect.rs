@@ -0,0 +1,223 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nix::libc;
+use thiserror::Error;
+
+use crate::{expand::ExpandError, scanner::RedirectOp};
+
+#[derive(Error, Debug)]
+pub enum RedirectError {
+    #[error(transparent)]
+    Expand(#[from] ExpandError),
+    #[error("{0}: {1}")]
+    Open(String, io::Error),
+    #[error("{0}: cannot overwrite existing file")]
+    Noclobber(String),
+    #[error("{0}: bad file descriptor")]
+    BadFd(String),
+    #[error("here-document: {0}")]
+    Content(io::Error),
+}
+
+/// Files opened for redirections are moved to descriptors at or above this,
+/// out of the way of the ones being redirected.
+const FIRST_PRIVATE_FD: RawFd = 10;
+
+#[derive(Debug)]
+enum Action {
+    /// Makes `fd` refer to an opened file.
+    Open {
+        file: OwnedFd,
+        fd: RawFd,
+    },
+    /// Makes `fd` a copy of `from`.
+    Dup {
+        from: RawFd,
+        fd: RawFd,
+    },
+    Close(RawFd),
+}
+
+impl Action {
+    fn fd(&self) -> RawFd {
+        match *self {
+            Action::Open { fd, .. } | Action::Dup { fd, .. } | Action::Close(fd) => fd,
+        }
+    }
+
+    /// Only makes system calls, so it is safe to run between fork and exec.
+    fn apply(&self) -> io::Result<()> {
+        // SAFETY: these only operate on descriptors, which are plain integers
+        // to the kernel.
+        let result = unsafe {
+            match *self {
+                Action::Open { ref file, fd } => libc::dup2(file.as_raw_fd(), fd),
+                Action::Dup { from, fd } => libc::dup2(from, fd),
+                Action::Close(fd) => {
+                    libc::close(fd);
+                    0
+                }
+            }
+        };
+
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The redirections of one command, opened and ready to be applied in the
+/// order they were written.
+#[derive(Debug, Default)]
+pub struct Redirections {
+    actions: Vec<Action>,
+}
+
+impl Redirections {
+    /// Opens `path` for a file redirection of `fd`. `&>` and `&>>` also point
+    /// stderr at the file.
+    pub fn open(
+        &mut self,
+        fd: RawFd,
+        op: RedirectOp,
+        path: &str,
+        noclobber: bool,
+    ) -> Result<(), RedirectError> {
+        let mut options = OpenOptions::new();
+        match op {
+            RedirectOp::Input => options.read(true),
+            RedirectOp::ReadWrite => options.read(true).write(true).create(true),
+            RedirectOp::Append | RedirectOp::AppendAll => options.append(true).create(true),
+            RedirectOp::Output | RedirectOp::OutputAll
+                if noclobber && fs::metadata(path).is_ok_and(|m| m.is_file()) =>
+            {
+                return Err(RedirectError::Noclobber(path.to_owned()));
+            }
+            _ => options.write(true).create(true).truncate(true),
+        };
+
+        let file = options
+            .open(path)
+            .map_err(|e| RedirectError::Open(path.to_owned(), e))?;
+        let file = private_fd(file).map_err(|e| RedirectError::Open(path.to_owned(), e))?;
+
+        self.actions.push(Action::Open { file, fd });
+        if matches!(op, RedirectOp::OutputAll | RedirectOp::AppendAll) {
+            self.actions.push(Action::Dup { from: fd, fd: 2 });
+        }
+        Ok(())
+    }
+
+    /// Feeds `content` to `fd`, for here-documents and here-strings.
+    pub fn content(&mut self, fd: RawFd, content: &str) -> Result<(), RedirectError> {
+        let file = content_file(content)
+            .and_then(private_fd)
+            .map_err(RedirectError::Content)?;
+        self.actions.push(Action::Open { file, fd });
+        Ok(())
+    }
+
+    /// Duplicates the descriptor named by `target` onto `fd`, or closes `fd`
+    /// if the target is `-`.
+    pub fn dup(&mut self, fd: RawFd, target: &str) -> Result<(), RedirectError> {
+        let action = match target {
+            "-" => Action::Close(fd),
+            _ => match target.parse() {
+                Ok(from) => Action::Dup { from, fd },
+                Err(_) => return Err(RedirectError::BadFd(target.to_owned())),
+            },
+        };
+        self.actions.push(action);
+        Ok(())
+    }
+
+    /// Applies the redirections to the current process. Meant to be called in
+    /// a child process before it runs a program.
+    pub fn apply(&self) -> io::Result<()> {
+        self.actions.iter().try_for_each(Action::apply)
+    }
+
+    /// Applies the redirections to the shell itself, for a builtin, keeping
+    /// copies of the descriptors they replace so they can be restored.
+    pub fn apply_saved(&self) -> Result<SavedFds, RedirectError> {
+        let _ = io::stdout().flush();
+        let mut saved = SavedFds::default();
+
+        for action in &self.actions {
+            let fd = action.fd();
+            if !saved.fds.iter().any(|(saved, _)| *saved == fd) {
+                // a closed descriptor has no copy and is closed again later
+                saved.fds.push((fd, private_copy(fd).ok()));
+            }
+            if action.apply().is_err() {
+                saved.restore();
+                return Err(RedirectError::BadFd(fd.to_string()));
+            }
+        }
+
+        Ok(saved)
+    }
+}
+
+/// Descriptors replaced by [`Redirections::apply_saved`].
+#[derive(Debug, Default)]
+pub struct SavedFds {
+    fds: Vec<(RawFd, Option<OwnedFd>)>,
+}
+
+impl SavedFds {
+    pub fn restore(self) {
+        let _ = io::stdout().flush();
+
+        for (fd, copy) in self.fds.into_iter().rev() {
+            let action = match copy {
+                Some(file) => Action::Open { file, fd },
+                None => Action::Close(fd),
+            };
+            let _ = action.apply();
+        }
+    }
+}
+
+/// Moves `file` to a close-on-exec descriptor of its own, so redirecting or
+/// closing low-numbered descriptors cannot clobber it.
+fn private_fd(file: File) -> io::Result<OwnedFd> {
+    private_copy(file.as_raw_fd())
+}
+
+pub(crate) fn private_copy(fd: RawFd) -> io::Result<OwnedFd> {
+    // SAFETY: on success fcntl returns a new descriptor that nothing else owns.
+    unsafe {
+        match libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, FIRST_PRIVATE_FD) {
+            -1 => Err(io::Error::last_os_error()),
+            copy => Ok(OwnedFd::from_raw_fd(copy)),
+        }
+    }
+}
+
+/// Puts here-document content in an unlinked temporary file, so it can be
+/// read as stdin without a writer having to run alongside the command.
+fn content_file(content: &str) -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("wsh-heredoc-{}-{n}", process::id()));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+
+    file.write_all(content.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
@@ -1,87 +1,798 @@
-use std::{iter::Peekable, str::Chars};
+use std::{iter::Peekable, str::CharIndices};
+
+use thiserror::Error;
+
+use crate::parser;
+
+/// A byte range into the scanned source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Renders the source line containing this span with a caret underline,
+    /// followed by `message`.
+    pub fn render(&self, source: &str, message: &str) -> String {
+        let start = self.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+
+        let column = source[line_start..start].chars().count();
+        let end = self.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+
+        format!(
+            "{line}\n{}{} {message}",
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+/// A redirection operator. All but `&>` and `&>>` may be preceded by a
+/// descriptor number, as in `2>>log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>|`, which overwrites files even with `noclobber` set
+    Clobber,
+    /// `>>`
+    Append,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `&>`, stdout and stderr to the same file
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+    /// `<<` and `<<-`
+    HereDoc,
+    /// `<<<`
+    HereString,
+}
+
+impl RedirectOp {
+    /// The descriptor redirected when no number is given.
+    pub fn default_fd(self) -> i32 {
+        match self {
+            RedirectOp::Input
+            | RedirectOp::ReadWrite
+            | RedirectOp::DupInput
+            | RedirectOp::HereDoc
+            | RedirectOp::HereString => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// Redirection operators, longest first so prefixes do not shadow them.
+const REDIRECT_OPS: [(&str, RedirectOp); 11] = [
+    ("<<<", RedirectOp::HereString),
+    ("&>>", RedirectOp::AppendAll),
+    ("<<", RedirectOp::HereDoc),
+    (">>", RedirectOp::Append),
+    (">|", RedirectOp::Clobber),
+    (">&", RedirectOp::DupOutput),
+    ("<>", RedirectOp::ReadWrite),
+    ("<&", RedirectOp::DupInput),
+    ("&>", RedirectOp::OutputAll),
+    (">", RedirectOp::Output),
+    ("<", RedirectOp::Input),
+];
 
 #[derive(Debug, PartialEq, Eq)]
-pub enum Token {
+pub enum TokenKind {
     Pipe,
-    Greater,
-    Less,
-    Literal(String),
+    /// `&&`
+    AndIf,
+    /// `||`
+    OrIf,
+    Semi,
+    /// `;;`, ends an arm of a `case`
+    DSemi,
+    /// `&`, runs the list before it in the background
+    Amp,
+    LParen,
+    RParen,
+    Newline,
+    /// A redirection operator, followed by its target word.
+    Redirect {
+        fd: Option<i32>,
+        op: RedirectOp,
+    },
+    /// The body of a `<<` or `<<-` here-document. It is single-quoted if the
+    /// delimiter was quoted, so nothing in it is expanded.
+    HereDoc {
+        fd: Option<i32>,
+        body: Word,
+    },
+    Literal(Word),
+    /// The expression inside `(( ... ))`.
+    Arith(String),
     Eof,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// A piece of a word, remembering how it was quoted in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    Unquoted(String),
+    SingleQuoted(String),
+    DoubleQuoted(String),
+    /// A single character protected by a backslash.
+    Escaped(char),
+}
+
+impl WordPart {
+    pub fn is_quoted(&self) -> bool {
+        !matches!(self, WordPart::Unquoted(_))
+    }
+}
+
+/// A shell word made of adjacent quoted and unquoted parts, e.g. `foo"bar"'baz'`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    pub fn new(parts: Vec<WordPart>) -> Self {
+        Word { parts }
+    }
+
+    /// The text of the word with all quoting removed.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Unquoted(s) | WordPart::SingleQuoted(s) | WordPart::DoubleQuoted(s) => {
+                    text.push_str(s)
+                }
+                WordPart::Escaped(c) => text.push(*c),
+            }
+        }
+        text
+    }
+
+    pub fn is_quoted(&self) -> bool {
+        self.parts.iter().any(WordPart::is_quoted)
+    }
+}
+
+impl From<&str> for Word {
+    fn from(value: &str) -> Self {
+        Word::new(vec![WordPart::Unquoted(value.to_owned())])
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScanErrorKind {
+    #[error("unterminated {0} quote")]
+    UnterminatedQuote(char),
+    #[error("unexpected character: {0}")]
+    UnexpectedChar(char),
+    #[error("unterminated arithmetic command")]
+    UnterminatedArithmetic,
+    #[error("expected a file name after {0}")]
+    BadRedirection(&'static str),
+    #[error("unterminated {0}")]
+    UnterminatedSubstitution(&'static str),
+    #[error("here-document delimited by end of input (wanted `{0}`)")]
+    UnterminatedHereDoc(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub span: Span,
+}
+
+impl ScanError {
+    fn new(kind: ScanErrorKind, start: usize, end: usize) -> Self {
+        ScanError {
+            kind,
+            span: Span::new(start, end),
+        }
+    }
+
+    /// Renders the offending line of `source` with a caret under the error.
+    pub fn render(&self, source: &str) -> String {
+        self.span.render(source, &self.kind.to_string())
+    }
+}
+
+/// A here-document whose body starts on the next line.
+struct PendingHereDoc {
+    /// Index of its token, which gets the body once it is read.
+    index: usize,
+    delimiter: Word,
+    strip_tabs: bool,
+    start: usize,
+}
+
 pub struct Scanner<'a> {
-    chars: Peekable<Chars<'a>>,
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    /// Treat blanks and metacharacters as ordinary word characters.
+    whole_word: bool,
+    /// End of the last redirection operator scanned.
+    operator_end: usize,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Scanner {
-            chars: source.chars().peekable(),
+            source,
+            chars: source.char_indices().peekable(),
+            whole_word: false,
+            operator_end: 0,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, String> {
-        let mut tokens = vec![];
+    /// Scans all of `source` as a single word in which blanks and
+    /// metacharacters are ordinary characters, as in the operand of
+    /// `${v:-word}`.
+    pub fn scan_whole_word(source: &'a str) -> Result<Word, ScanError> {
+        let mut scanner = Scanner::new(source);
+        scanner.whole_word = true;
+        scanner.scan_word()
+    }
+
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, ScanError> {
+        let mut tokens: Vec<Token> = vec![];
+        let mut pending = vec![];
 
-        while let Some(c) = self.chars.next() {
-            let token = match c {
-                ' ' | '\n' | '\t' => continue,
-                '|' => Token::Pipe,
-                '>' => Token::Greater,
-                '<' => Token::Less,
-                x => {
-                    if !self.is_valid_literal_start(&x) {
-                        return Err(format!("unexpected token: {x}"));
+        while let Some(&(start, c)) = self.chars.peek() {
+            let kind = match c {
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                    if c != '\n' {
+                        continue;
+                    }
+                    for heredoc in pending.drain(..) {
+                        let body = self.scan_heredoc_body(&heredoc)?;
+                        if let TokenKind::HereDoc { body: slot, .. } =
+                            &mut tokens[heredoc.index].kind
+                        {
+                            *slot = body;
+                        }
                     }
-                    Token::Literal(self.scan_literal(x))
+                    tokens.push(Token {
+                        kind: TokenKind::Newline,
+                        span: Span::new(start, start + 1),
+                    });
+                    continue;
+                }
+                '|' if self.source[start..].starts_with("||") => {
+                    self.advance_to(start + 2);
+                    TokenKind::OrIf
+                }
+                '&' if self.source[start..].starts_with("&&") => {
+                    self.advance_to(start + 2);
+                    TokenKind::AndIf
+                }
+                ';' if self.source[start..].starts_with(";;") => {
+                    self.advance_to(start + 2);
+                    TokenKind::DSemi
+                }
+                ';' => {
+                    self.chars.next();
+                    TokenKind::Semi
+                }
+                '|' => {
+                    self.chars.next();
+                    TokenKind::Pipe
+                }
+                '<' | '>' | '&' | '0'..='9' if self.redirect_at(start).is_some() => {
+                    self.scan_redirect(start, &mut pending, tokens.len())?
+                }
+                '&' => {
+                    self.chars.next();
+                    TokenKind::Amp
                 }
+                '(' if self.source[start..].starts_with("((") => {
+                    TokenKind::Arith(self.scan_arith(start)?)
+                }
+                '(' => {
+                    self.chars.next();
+                    TokenKind::LParen
+                }
+                ')' => {
+                    self.chars.next();
+                    TokenKind::RParen
+                }
+                '#' => {
+                    // a comment runs to the end of the line
+                    while self.chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                    continue;
+                }
+                x if Self::is_metachar(x) => {
+                    return Err(ScanError::new(
+                        ScanErrorKind::UnexpectedChar(x),
+                        start,
+                        start + x.len_utf8(),
+                    ));
+                }
+                _ => TokenKind::Literal(self.scan_word()?),
             };
 
-            tokens.push(token);
+            let end = match kind {
+                // the span of an operator should not cover the blanks after it
+                TokenKind::Redirect { .. } => self.operator_end,
+                _ => self.pos(),
+            };
+            tokens.push(Token {
+                kind,
+                span: Span::new(start, end),
+            });
         }
 
-        tokens.push(Token::Eof);
+        if let Some(heredoc) = pending.first() {
+            return Err(ScanError::new(
+                ScanErrorKind::UnterminatedHereDoc(heredoc.delimiter.text()),
+                heredoc.start,
+                self.source.len(),
+            ));
+        }
+
+        let end = self.source.len();
+        tokens.push(Token {
+            kind: TokenKind::Eof,
+            span: Span::new(end, end),
+        });
 
         Ok(tokens)
     }
 
-    fn scan_literal(&mut self, start: char) -> String {
-        if start == '"' {
-            return self.scan_string();
+    /// Byte offset of the next unread character.
+    fn pos(&mut self) -> usize {
+        self.chars.peek().map_or(self.source.len(), |&(i, _)| i)
+    }
+
+    /// Finds the redirection operator at `start`, with its descriptor number
+    /// and the length of both together.
+    fn redirect_at(&self, start: usize) -> Option<(Option<i32>, &'static str, RedirectOp, usize)> {
+        let rest = &self.source[start..];
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let fd = match digits {
+            0 => None,
+            _ => Some(rest[..digits].parse().ok()?),
+        };
+
+        let (text, op) = REDIRECT_OPS
+            .into_iter()
+            .find(|(text, _)| rest[digits..].starts_with(text))?;
+        if fd.is_some() && text.starts_with('&') {
+            return None;
+        }
+
+        Some((fd, text, op, digits + text.len()))
+    }
+
+    /// Scans a redirection operator and checks that its target follows. The
+    /// delimiter of a here-document is scanned here as well, and its body
+    /// once the line ends.
+    fn scan_redirect(
+        &mut self,
+        start: usize,
+        pending: &mut Vec<PendingHereDoc>,
+        index: usize,
+    ) -> Result<TokenKind, ScanError> {
+        let (fd, text, op, len) = self.redirect_at(start).unwrap();
+        self.advance_to(start + len);
+        self.operator_end = start + len;
+
+        if op == RedirectOp::HereDoc {
+            let strip_tabs = self.chars.next_if(|&(_, c)| c == '-').is_some();
+            pending.push(PendingHereDoc {
+                index,
+                delimiter: self.scan_heredoc_delimiter(start)?,
+                strip_tabs,
+                start,
+            });
+            return Ok(TokenKind::HereDoc {
+                fd,
+                body: Word::default(),
+            });
+        }
+
+        self.expect_redirection_target(text, start)?;
+        Ok(TokenKind::Redirect { fd, op })
+    }
+
+    /// Consumes characters up to the byte offset `end`.
+    fn advance_to(&mut self, end: usize) {
+        while self.chars.next_if(|&(i, _)| i < end).is_some() {}
+    }
+
+    /// Makes sure a redirection operator is followed by a word.
+    fn expect_redirection_target(
+        &mut self,
+        op: &'static str,
+        start: usize,
+    ) -> Result<(), ScanError> {
+        while self.chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+
+        match self.chars.peek() {
+            Some(&(_, c)) if !Self::is_metachar(c) => Ok(()),
+            _ => Err(ScanError::new(
+                ScanErrorKind::BadRedirection(op),
+                start,
+                self.operator_end,
+            )),
         }
+    }
 
-        let mut literal = String::new();
-        literal.push(start);
+    /// Scans the delimiter word after `<<`, which must be on the same line.
+    fn scan_heredoc_delimiter(&mut self, start: usize) -> Result<Word, ScanError> {
+        while self
+            .chars
+            .next_if(|&(_, c)| c == ' ' || c == '\t')
+            .is_some()
+        {}
+
+        match self.chars.peek() {
+            Some(&(_, c)) if !Self::is_metachar(c) => self.scan_word(),
+            _ => Err(ScanError::new(
+                ScanErrorKind::BadRedirection("<<"),
+                start,
+                self.operator_end,
+            )),
+        }
+    }
+
+    /// Reads here-document lines up to the one matching its delimiter.
+    fn scan_heredoc_body(&mut self, heredoc: &PendingHereDoc) -> Result<Word, ScanError> {
+        let delimiter = heredoc.delimiter.text();
+        let mut body = String::new();
+
+        loop {
+            let line_start = self.pos();
+            if line_start == self.source.len() {
+                return Err(ScanError::new(
+                    ScanErrorKind::UnterminatedHereDoc(delimiter),
+                    heredoc.start,
+                    line_start,
+                ));
+            }
+
+            let rest = &self.source[line_start..];
+            let (line, len) = match rest.find('\n') {
+                Some(i) => (&rest[..i], i + 1),
+                None => (rest, rest.len()),
+            };
+            self.advance_to(line_start + len);
 
-        while let Some(&c) = self.chars.peek() {
-            if self.is_valid_literal_char(&c) {
-                literal.push(self.chars.next().unwrap());
+            let line = if heredoc.strip_tabs {
+                line.trim_start_matches('\t')
             } else {
+                line
+            };
+            if line == delimiter {
                 break;
             }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        Ok(if heredoc.delimiter.is_quoted() {
+            Word::new(vec![WordPart::SingleQuoted(body)])
+        } else {
+            heredoc_word(&body)
+        })
+    }
+
+    /// Scans the expression of a `(( ... ))` command up to the matching `))`.
+    fn scan_arith(&mut self, start: usize) -> Result<String, ScanError> {
+        self.chars.next();
+        self.chars.next();
+
+        let mut expr = String::new();
+        let mut depth = 0;
+
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' if self.chars.next_if(|&(_, c)| c == ')').is_some() => return Ok(expr),
+                _ => (),
+            }
+            expr.push(c);
+        }
+
+        Err(ScanError::new(
+            ScanErrorKind::UnterminatedArithmetic,
+            start,
+            self.source.len(),
+        ))
+    }
+
+    /// Scans adjacent quoted and unquoted segments into a single word.
+    fn scan_word(&mut self) -> Result<Word, ScanError> {
+        let mut parts = vec![];
+        let mut unquoted = String::new();
+
+        while let Some(&(start, c)) = self.chars.peek() {
+            match c {
+                '\'' | '"' | '\\' => {
+                    if !unquoted.is_empty() {
+                        parts.push(WordPart::Unquoted(std::mem::take(&mut unquoted)));
+                    }
+                    self.chars.next();
+                    match c {
+                        '\'' => parts.push(WordPart::SingleQuoted(self.scan_single_quoted(start)?)),
+                        '"' => self.scan_double_quoted(start, &mut parts)?,
+                        _ => match self.chars.next() {
+                            // line continuation
+                            Some((_, '\n')) => (),
+                            Some((_, escaped)) => parts.push(WordPart::Escaped(escaped)),
+                            None => unquoted.push('\\'),
+                        },
+                    }
+                }
+                '$' | '`' => match self.scan_substitution(start)? {
+                    Some(raw) => unquoted.push_str(raw),
+                    None => {
+                        unquoted.push(c);
+                        self.chars.next();
+                    }
+                },
+                c if !self.whole_word && Self::is_metachar(c) => break,
+                c => {
+                    unquoted.push(c);
+                    self.chars.next();
+                }
+            }
+        }
+
+        if !unquoted.is_empty() {
+            parts.push(WordPart::Unquoted(unquoted));
         }
 
-        literal
+        Ok(Word::new(parts))
     }
 
-    fn scan_string(&mut self) -> String {
+    fn scan_single_quoted(&mut self, start: usize) -> Result<String, ScanError> {
         let mut string = String::new();
-        for c in &mut self.chars {
-            if c == '"' {
-                self.chars.next();
-                break;
+        for (_, c) in &mut self.chars {
+            if c == '\'' {
+                return Ok(string);
             }
             string.push(c);
         }
 
-        string
+        Err(self.unterminated('\'', start))
+    }
+
+    /// Scans the body of a double-quoted string. A backslash only escapes
+    /// `$`, `` ` ``, `"`, `\` and newlines here, so the string may be split
+    /// around escaped characters.
+    fn scan_double_quoted(
+        &mut self,
+        start: usize,
+        parts: &mut Vec<WordPart>,
+    ) -> Result<(), ScanError> {
+        let mut string = String::new();
+        // an empty "" still produces a (quoted) part
+        let mut pushed = false;
+
+        loop {
+            let Some((i, c)) = self.chars.next() else {
+                return Err(self.unterminated('"', start));
+            };
+
+            match c {
+                '"' => break,
+                '$' | '`' => match self.scan_substitution(i)? {
+                    Some(raw) => string.push_str(raw),
+                    None => string.push(c),
+                },
+                '\\' => match self.chars.peek() {
+                    Some((_, '\n')) => {
+                        self.chars.next();
+                    }
+                    Some(&(_, escaped @ ('$' | '`' | '"' | '\\'))) => {
+                        self.chars.next();
+                        if !string.is_empty() {
+                            parts.push(WordPart::DoubleQuoted(std::mem::take(&mut string)));
+                        }
+                        parts.push(WordPart::Escaped(escaped));
+                        pushed = true;
+                    }
+                    _ => string.push(c),
+                },
+                c => string.push(c),
+            }
+        }
+
+        if !string.is_empty() || !pushed {
+            parts.push(WordPart::DoubleQuoted(string));
+        }
+
+        Ok(())
+    }
+
+    /// Consumes the `$(...)`, `${...}` or `` `...` `` starting at `start`
+    /// and returns its raw text, which is expanded later. Returns `None` if
+    /// there is no substitution there.
+    fn scan_substitution(&mut self, start: usize) -> Result<Option<&'a str>, ScanError> {
+        let rest = &self.source[start..];
+        let Some(opener) = ["$(", "${", "`"].into_iter().find(|o| rest.starts_with(o)) else {
+            return Ok(None);
+        };
+
+        let Some(len) = substitution_len(rest) else {
+            return Err(ScanError::new(
+                ScanErrorKind::UnterminatedSubstitution(opener),
+                start,
+                self.source.len(),
+            ));
+        };
+
+        self.advance_to(start + len);
+        Ok(Some(&rest[..len]))
+    }
+
+    fn unterminated(&self, quote: char, start: usize) -> ScanError {
+        ScanError::new(
+            ScanErrorKind::UnterminatedQuote(quote),
+            start,
+            self.source.len(),
+        )
+    }
+
+    /// Characters that end an unquoted word. Quotes and backslashes are
+    /// handled separately since they continue the current word.
+    fn is_metachar(c: char) -> bool {
+        c.is_whitespace() || "|<>&;()".contains(c)
+    }
+}
+
+/// Length in bytes of the `$(...)`, `${...}` or `` `...` `` at the start of
+/// `text`, skipping over quoted text and nested substitutions. Returns `None`
+/// if `text` does not start with one or it is never closed.
+pub fn substitution_len(text: &str) -> Option<usize> {
+    if text.starts_with('`') {
+        let mut chars = text.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '`' => return Some(i + 1),
+                _ => (),
+            }
+        }
+        return None;
+    }
+
+    // `$((` is arithmetic, whose parentheses only nest
+    if text.starts_with("$(") && !text.starts_with("$((") {
+        return command_substitution_len(text);
+    }
+
+    let (open, close) = if text.starts_with("$(") {
+        ('(', ')')
+    } else if text.starts_with("${") {
+        ('{', '}')
+    } else {
+        return None;
+    };
+
+    let mut depth = 1;
+    let mut i = 2;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        i += match c {
+            '$' | '`' if let Some(len) = substitution_len(rest) => len,
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            '\'' if open == '(' => rest[1..].find('\'')? + 2,
+            '"' => double_quoted_len(rest)?,
+            c if c == open => {
+                depth += 1;
+                1
+            }
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+                1
+            }
+            c => c.len_utf8(),
+        };
+    }
+
+    None
+}
+
+/// Length in bytes of the `$(...)` at the start of `text`. It ends at the
+/// first `)` before which the body is a whole command list, so a `)` in
+/// quotes or after a `case` pattern does not end it.
+fn command_substitution_len(text: &str) -> Option<usize> {
+    text.char_indices().skip(2).find_map(|(i, c)| {
+        if c != ')' {
+            return None;
+        }
+        let body = &text[2..i];
+        let tokens = Scanner::new(body).scan_tokens().ok()?;
+        parser::parse(&tokens, body).ok().map(|_| i + 1)
+    })
+}
+
+/// Length in bytes of the double-quoted string at the start of `text`.
+fn double_quoted_len(text: &str) -> Option<usize> {
+    let mut i = 1;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        i += match c {
+            '"' => return Some(i + 1),
+            '$' | '`' if let Some(len) = substitution_len(rest) => len,
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            c => c.len_utf8(),
+        };
     }
+    None
+}
+
+/// Splits an unquoted here-document body into parts. It is expanded like
+/// double-quoted text, except that `"` is not special and a backslash only
+/// escapes `$`, `` ` ``, `\` and newlines.
+fn heredoc_word(body: &str) -> Word {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut i = 0;
+
+    while let Some(c) = body[i..].chars().next() {
+        let rest = &body[i..];
+        if let Some(len) = substitution_len(rest) {
+            text.push_str(&rest[..len]);
+            i += len;
+            continue;
+        }
 
-    fn is_valid_literal_char(&self, start: &char) -> bool {
-        start.is_alphanumeric() || "_-=./:{}\\*;".contains(*start)
+        match (c, rest[c.len_utf8()..].chars().next()) {
+            ('\\', Some('\n')) => i += 2,
+            ('\\', Some(escaped @ ('$' | '`' | '\\'))) => {
+                if !text.is_empty() {
+                    parts.push(WordPart::DoubleQuoted(std::mem::take(&mut text)));
+                }
+                parts.push(WordPart::Escaped(escaped));
+                i += 2;
+            }
+            _ => {
+                text.push(c);
+                i += c.len_utf8();
+            }
+        }
     }
 
-    fn is_valid_literal_start(&self, start: &char) -> bool {
-        start.is_alphanumeric() || "_-=.\"/:{}\\*;".contains(*start)
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(WordPart::DoubleQuoted(text));
     }
+
+    Word::new(parts)
 }
@@ -1,23 +1,71 @@
 use std::{
     collections::HashMap,
+    fmt::Display,
     fs::File,
-    io,
-    process::{self, ChildStdout, Stdio},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    rc::Rc,
+    slice,
+    sync::Arc,
 };
 
-use rustyline::{DefaultEditor, error::ReadlineError};
+use nix::{
+    libc,
+    sys::{
+        signal::{Signal, killpg},
+        wait::{WaitStatus, waitpid},
+    },
+    unistd::{self, ForkResult, Pid},
+};
+use rustyline::error::ReadlineError;
 
 use crate::{
     Config,
-    commands::{Command, CommandIO, builtins},
-    scanner::Scanner,
+    arith::{self, ArithError},
+    commands::{Command, Functions, builtins},
+    debug::{DebugCommand, Debugger},
+    editor::{self, LineReader},
+    expand::{self, ExpandError},
+    glob::{self, GlobOptions},
+    jobs::{self, Job, JobError, JobState, JobTable},
+    options::Options,
+    parser::{self, AndOr, CaseArm, Compound, Connector, List, Pipeline, Redirect, SimpleCommand},
+    redirect::{self, RedirectError, Redirections},
+    scanner::{RedirectOp, Scanner, Word},
+    vars::Variables,
     wish::{CmdGen, WishError},
 };
 
 enum ExecError {
-    Exit,
-    CommandNotFound(String),
-    PermissionError,
+    /// `exit n`, or a failure with `set -e`, ending the shell.
+    Exit(i32),
+    /// `break n`, unwinding to the nth enclosing loop.
+    Break(usize),
+    /// `continue n`, unwinding to the nth enclosing loop.
+    Continue(usize),
+    /// `return n`, leaving the function being run.
+    Return(i32),
+}
+
+/// How deep function calls may nest, so runaway recursion is reported
+/// instead of overflowing the stack.
+const MAX_FUNCTION_DEPTH: usize = 200;
+
+/// Variables assigned in front of a command, for it alone.
+type Assignments = Vec<(String, String)>;
+
+/// A pipeline stage ready to run.
+enum Stage<'a> {
+    Command(Command, Assignments),
+    Compound(&'a Compound),
+    Arith(&'a str),
+    Define(&'a str, &'a Rc<parser::Function>),
 }
 
 enum ShellMode {
@@ -27,8 +75,51 @@ enum ShellMode {
 
 pub struct Shell {
     prompt: String,
-    line_reader: DefaultEditor,
-    env_vars: HashMap<String, String>,
+    line_reader: LineReader,
+    vars: Variables,
+    glob_options: GlobOptions,
+    options: Options,
+    jobs: JobTable,
+    /// Set when the shell is interactive and puts each job in a process
+    /// group of its own, handing the terminal to the one in the foreground.
+    job_control: bool,
+    /// A private copy of the terminal, kept for handing it over.
+    terminal: Option<OwnedFd>,
+    /// The process of the last job started in the background, for `$!`.
+    last_background: Option<Pid>,
+    /// How many loops are running, which is as far as `break` can reach.
+    loop_depth: usize,
+    /// How many commands are running whose status is tested, as conditions
+    /// or on the left of `&&` and `||`, where `set -e` does not apply.
+    conditions: usize,
+    functions: Functions,
+    /// `$0`, the name of the shell or of the script it runs.
+    name: String,
+    /// `$1` to `$n`.
+    positional: Vec<String>,
+    /// The exit status of the last pipeline.
+    status: i32,
+    /// The status of each stage of the last pipeline, for `PIPESTATUS`.
+    pipestatus: Vec<i32>,
+    /// The status of the last command substitution, which is that of a
+    /// command made only of assignments.
+    substitution_status: Option<i32>,
+    /// `$$`, which subshells share with the shell that started them.
+    pid: Pid,
+    /// How many `source` commands are running, which `return` may leave.
+    sourcing: usize,
+    /// The line of the script the input being parsed starts on.
+    first_line: usize,
+    /// The line of the pipeline running, for `LINENO`.
+    lineno: usize,
+    /// The names of the functions being run, innermost last, for
+    /// `FUNCNAME`.
+    call_stack: Vec<String>,
+    /// Set with `--debug` to stop before commands.
+    debugger: Option<Debugger>,
+    /// A frame for each function being run, holding the values variables
+    /// had before `local` shadowed them.
+    locals: Vec<HashMap<String, Option<String>>>,
     cmd_gen: CmdGen,
     mode: ShellMode,
 }
@@ -37,55 +128,99 @@ impl Shell {
     pub fn new(config: Config) -> Shell {
         Shell {
             prompt: config.prompt,
-            line_reader: DefaultEditor::new().expect("error creating line editor"),
-            env_vars: HashMap::new(),
+            line_reader: editor::line_reader().expect("error creating line editor"),
+            vars: Variables::from_env(),
+            glob_options: GlobOptions::default(),
+            options: Options::default(),
+            jobs: JobTable::default(),
+            job_control: false,
+            terminal: None,
+            last_background: None,
+            loop_depth: 0,
+            conditions: 0,
+            functions: Functions::new(),
+            name: "wsh".to_owned(),
+            positional: vec![],
+            status: 0,
+            pipestatus: vec![0],
+            substitution_status: None,
+            pid: unistd::getpid(),
+            sourcing: 0,
+            first_line: 1,
+            lineno: 0,
+            call_stack: vec![],
+            debugger: None,
+            locals: vec![],
             cmd_gen: CmdGen::new(),
             mode: ShellMode::Normal,
         }
     }
 
-    pub async fn run(&mut self) -> Result<(), io::Error> {
+    /// Sets `$0` and the positional parameters, for a script or `-c`.
+    pub fn set_args(&mut self, name: String, args: Vec<String>) {
+        self.name = name;
+        self.positional = args;
+    }
+
+    /// Starts the step debugger, which stops before the first command.
+    pub fn enable_debugger(&mut self) {
+        self.debugger = Some(Debugger::new());
+    }
+
+    /// Runs a script file, returning the status of the last command.
+    pub fn run_file(&mut self, path: &Path) -> Result<i32, io::Error> {
+        let file = File::open(path)?;
+        let result = self.run_script(BufReader::new(file));
+        Ok(self.exit_status(result))
+    }
+
+    /// Runs the commands given with `-c`, returning the status of the last
+    /// one.
+    pub fn run_string(&mut self, command: &str) -> i32 {
+        let result = self.eval(command);
+        self.exit_status(result)
+    }
+
+    /// Reads commands interactively or, if standard input is not a
+    /// terminal, runs it as a script. Returns the status of the last
+    /// command.
+    pub async fn run(&mut self) -> Result<i32, io::Error> {
+        if !io::stdin().is_terminal() {
+            // the debugger reads its commands from standard input too
+            if self.debugger.is_some() {
+                eprintln!("wsh: --debug: the script must be a file or -c");
+                return Ok(2);
+            }
+            let result = self.run_script(script_stdin()?);
+            return Ok(self.exit_status(result));
+        }
+
+        self.init_job_control();
+
         loop {
             match self.mode {
                 ShellMode::Normal => {
-                    let input = match self.line_reader.readline(&self.prompt) {
-                        Ok(line) => line,
+                    self.notify_jobs();
+                    let input = match self.read_input() {
+                        Ok(input) => input,
                         Err(ReadlineError::Interrupted) => continue,
                         Err(ReadlineError::Eof) => break,
                         Err(err) => {
-                            println!("error: {:?}", err);
+                            eprintln!("error: {:?}", err);
                             break;
                         }
                     };
 
-                    let input = self.expand(&input);
-
-                    let mut scanner = Scanner::new(input.as_str());
-
-                    let tokens = match scanner.scan_tokens() {
-                        Ok(tokens) => tokens,
-                        Err(e) => {
-                            println!("{}", e);
-                            continue;
-                        }
-                    };
-
-                    let cmds = Command::process_input(tokens);
-
-                    match self.execute(cmds).await {
-                        Err(ExecError::Exit) => break,
-                        Err(ExecError::PermissionError) => println!("permission denied"),
-                        Err(ExecError::CommandNotFound(cmd)) => {
-                            println!("command not found: {cmd}")
-                        }
-                        _ => (),
-                    };
+                    if let Err(ExecError::Exit(status)) = self.eval(&input) {
+                        self.status = status;
+                        break;
+                    }
                 }
                 ShellMode::Wish => {
-                    let input = match self.line_reader.readline(">> ") {
+                    let input = match self.read_text(">> ") {
                         Ok(line) => line,
                         Err(err) => {
-                            println!("error: {:?}", err);
+                            eprintln!("error: {:?}", err);
                             self.mode = ShellMode::Normal;
                             continue;
                         }
@@ -103,19 +238,94 @@ impl Shell {
                         Ok(commands) => {
                             self.request_commands_execution(commands).await;
                         }
-                        Err(WishError::Gemini(e)) => println!("{e}"),
-                        Err(e) => println!("{e}"),
+                        Err(WishError::Gemini(e)) => eprintln!("{e}"),
+                        Err(e) => eprintln!("{e}"),
                     }
                 }
             }
         }
-        Ok(())
+        Ok(self.status)
+    }
+
+    /// The status the shell exits with once it has run `result`: that of
+    /// `exit` or else of the last command.
+    fn exit_status(&self, result: Result<i32, ExecError>) -> i32 {
+        match result {
+            Err(ExecError::Exit(status)) => status,
+            _ => self.status,
+        }
+    }
+
+    /// Runs commands read from a script until its end or `exit`. A `#!`
+    /// line at the start is skipped.
+    fn run_script(&mut self, mut reader: impl BufRead) -> Result<i32, ExecError> {
+        let first_line = self.first_line;
+        let mut line = 1;
+        let mut result = Ok(0);
+
+        while let Some(input) = read_complete(&mut reader) {
+            let mut text = input.as_str();
+            self.first_line = line;
+            line += input.matches('\n').count() + 1;
+            if self.first_line == 1 && input.starts_with("#!") {
+                text = input.split_once('\n').map_or("", |(_, rest)| rest);
+                self.first_line = 2;
+            }
+            result = self.eval(text);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.first_line = first_line;
+        result
+    }
+
+    /// Reads a command, which may go on over several lines. The editor's
+    /// validator keeps incomplete input open, so its earlier lines can still
+    /// be edited. rustyline draws no prompt before the lines after the
+    /// first, though, so once `PS2` is set each of them is read on its own
+    /// after that prompt instead.
+    fn read_input(&mut self) -> Result<String, ReadlineError> {
+        let Some(ps2) = self.vars.get("PS2").map(str::to_owned) else {
+            return self.line_reader.readline(&self.prompt);
+        };
+
+        let prompt = self.prompt.clone();
+        let mut input = self.read_text(&prompt)?;
+        while parser::is_incomplete(&input) {
+            match self.read_text(&ps2) {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                // the parser reports what is missing
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(input)
+    }
+
+    /// Reads a line that the validator does not hold open, which Enter
+    /// always accepts.
+    fn read_text(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        let set_validation = |shell: &mut Shell, enabled| {
+            if let Some(validator) = shell.line_reader.helper_mut() {
+                validator.enabled = enabled;
+            }
+        };
+        set_validation(self, false);
+        let result = self.line_reader.readline(prompt);
+        set_validation(self, true);
+        result
     }
 
     async fn request_commands_execution(&mut self, commands: Vec<String>) {
         for c in commands {
             println!("\n-> {c}");
-            let decision = match self.line_reader.readline("Execute? [y/N] ") {
+            let decision = match self.read_text("Execute? [y/N] ") {
                 Ok(decision) => decision,
                 Err(_) => {
                     println!("stopping execution");
@@ -125,170 +335,1709 @@ impl Shell {
             println!();
             match decision.to_lowercase().as_str() {
                 "y" | "yes" => {
-                    let mut scanner = Scanner::new(c.as_str());
+                    if let Err(ExecError::Exit(status)) = self.eval(&c) {
+                        self.status = status;
+                        break;
+                    }
+                }
+                // not yes, break
+                _ => break,
+            };
+        }
+    }
+
+    /// Scans, parses and executes a line of input. Nothing runs if it has
+    /// a syntax error.
+    fn eval(&mut self, input: &str) -> Result<i32, ExecError> {
+        let tokens = match Scanner::new(input).scan_tokens() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                eprintln!("{}", e.render(input));
+                self.status = 2;
+                return Ok(2);
+            }
+        };
+
+        let program = match parser::parse_at(&tokens, input, self.first_line) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}", e.render(input));
+                self.status = 2;
+                return Ok(2);
+            }
+        };
+
+        self.run_list(&program.body)
+    }
+
+    /// Runs and-or lists one after another, returning the status of the
+    /// last pipeline that ran.
+    fn run_list(&mut self, list: &List) -> Result<i32, ExecError> {
+        let mut status = 0;
+
+        for and_or in &list.items {
+            status = if and_or.background {
+                self.run_background(and_or)
+            } else {
+                self.run_and_or(and_or)?
+            };
+        }
+
+        Ok(status)
+    }
 
-                    let tokens = match scanner.scan_tokens() {
-                        Ok(tokens) => tokens,
+    /// Runs the pipelines of an and-or list as far as their statuses allow.
+    /// All but the last are tested, so `set -e` leaves them alone.
+    fn run_and_or(&mut self, and_or: &AndOr) -> Result<i32, ExecError> {
+        let pipelines = std::iter::once((None, &and_or.first))
+            .chain(and_or.rest.iter().map(|(c, p)| (Some(c), p)));
+        let last = and_or.rest.len();
+        let mut status = 0;
+
+        for (i, (connector, pipeline)) in pipelines.enumerate() {
+            let run = match connector {
+                None => true,
+                Some(Connector::And) => status == 0,
+                Some(Connector::Or) => status != 0,
+            };
+            if !run {
+                continue;
+            }
+            status = match i == last {
+                true => self.run_pipeline(pipeline)?,
+                false => self.run_condition(|shell| shell.run_pipeline(pipeline))?,
+            };
+        }
+        Ok(status)
+    }
+
+    /// Runs `f` as a condition, whose failure `set -e` ignores.
+    fn run_condition<T>(&mut self, f: impl FnOnce(&mut Shell) -> T) -> T {
+        self.conditions += 1;
+        let result = f(self);
+        self.conditions -= 1;
+        result
+    }
+
+    /// Runs an and-or list in a forked copy of the shell without waiting for
+    /// it, adding it to the job table.
+    fn run_background(&mut self, and_or: &AndOr) -> i32 {
+        let _ = io::stdout().flush();
+
+        // SAFETY: the shell is single threaded, so the child can keep using
+        // the allocator and the standard streams.
+        match unsafe { unistd::fork() } {
+            Ok(ForkResult::Child) => {
+                if self.job_control {
+                    let _ = unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                } else if let Ok(null) = File::open("/dev/null") {
+                    // without job control a background job must not read
+                    // the shell's input
+                    let _ = unistd::dup2_stdin(&null);
+                }
+                self.enter_subshell();
+
+                let status = match self.run_and_or(and_or) {
+                    Ok(status) | Err(ExecError::Exit(status)) => status,
+                    Err(_) => 0,
+                };
+                let _ = io::stdout().flush();
+                process::exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                if self.job_control {
+                    // also done by the child, whichever runs first
+                    let _ = unistd::setpgid(child, child);
+                }
+                let id = self
+                    .jobs
+                    .add(Job::new(child, vec![child], and_or.text.clone()));
+                if self.job_control {
+                    println!("[{id}] {child}");
+                }
+                self.last_background = Some(child);
+                0
+            }
+            Err(e) => {
+                eprintln!("fork: {e}");
+                1
+            }
+        }
+    }
+
+    /// Runs a pipeline and sets `$?`. With `set -e` a failure ends the
+    /// shell, unless the pipeline is negated or tested.
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> Result<i32, ExecError> {
+        self.lineno = pipeline.line;
+        if self.debugger.is_some() {
+            self.debug_stop(pipeline)?;
+        }
+        let status = self.execute(&pipeline.commands, &pipeline.text)?;
+        self.status = match pipeline.negated {
+            true => (status == 0) as i32,
+            false => status,
+        };
+        if self.options.errexit && self.status != 0 && !pipeline.negated && self.conditions == 0 {
+            return Err(ExecError::Exit(self.status));
+        }
+        Ok(self.status)
+    }
+
+    /// Executes a pipeline, returning the exit status of its last command.
+    /// With job control its processes share a process group, which is given
+    /// the terminal while it runs.
+    fn execute(&mut self, cmds: &[parser::Command], text: &str) -> Result<i32, ExecError> {
+        let mut prev_stdout: Option<OwnedFd> = None;
+        let mut pids = Vec::new();
+        let mut pgid: Option<Pid> = None;
+        let mut status = 0;
+
+        let last = cmds.len().saturating_sub(1);
+        let mut last_is_process = false;
+        // the status of each stage, and the stage each process runs
+        let mut pipestatus = Vec::with_capacity(cmds.len());
+        let mut stages = Vec::new();
+
+        for (i, cmd) in cmds.iter().enumerate() {
+            if i > 0 {
+                pipestatus.push(status);
+            }
+            status = 0;
+            self.substitution_status = None;
+            let (stage, redirections) = match self.resolve(cmd) {
+                Ok(Some(resolved)) => resolved,
+                Ok(None) => {
+                    status = self.substitution_status.unwrap_or(0);
+                    continue;
+                }
+                Err(e) => {
+                    let fatal = matches!(&e, RedirectError::Expand(e) if e.is_fatal());
+                    status = self.expansion_failed(e, fatal)?;
+                    continue;
+                }
+            };
+            // every stage of a longer pipeline runs in a process of its own,
+            // but a builtin or function on its own runs in the shell, so it
+            // can change it
+            let forked = last > 0
+                || matches!(
+                    stage,
+                    Stage::Command(Command::External(_), _)
+                        | Stage::Compound(Compound::Subshell(_))
+                );
+            if i == last {
+                last_is_process = forked;
+            }
+
+            let started = match stage {
+                Stage::Command(Command::External(args), assignments) => self.spawn(
+                    &args,
+                    &assignments,
+                    redirections,
+                    prev_stdout.take(),
+                    i != last,
+                    pgid,
+                ),
+                stage if forked => self
+                    .fork_stage(stage, redirections, prev_stdout.take(), i != last, pgid)
+                    .map_err(|e| {
+                        eprintln!("fork: {e}");
+                        1
+                    }),
+                stage => {
+                    let saved = match redirections.apply_saved() {
+                        Ok(saved) => saved,
                         Err(e) => {
-                            println!("{}", e);
+                            eprintln!("{e}");
+                            status = 1;
                             continue;
                         }
                     };
+                    let result = self.run_stage(stage);
+                    saved.restore();
+                    status = result?;
+                    continue;
+                }
+            };
+            match started {
+                Ok((pid, stdout)) => {
+                    pgid.get_or_insert(pid);
+                    pids.push(pid);
+                    stages.push(i);
+                    prev_stdout = stdout;
+                }
+                Err(code) => status = code,
+            }
+        }
 
-                    let cmds = Command::process_input(tokens);
+        pipestatus.push(status);
+        // the pipe to a command that failed to start is closed by now
+        drop(prev_stdout);
 
-                    match self.execute(cmds).await {
-                        Err(ExecError::Exit) => break,
-                        Err(ExecError::CommandNotFound(cmd)) => {
-                            println!("command not found: {cmd}")
-                        }
+        let Some(pgid) = pgid else {
+            return Ok(self.set_pipestatus(pipestatus, status));
+        };
+
+        let mut job = Job::new(pgid, pids, text.to_owned());
+        job.wait(self.job_control);
+        self.reclaim_terminal();
+
+        for (&i, process) in stages.iter().zip(&job.processes) {
+            pipestatus[i] = process.status.unwrap_or(128 + Signal::SIGTSTP as i32);
+        }
+
+        if job.state == JobState::Stopped {
+            job.changed = false;
+            let id = self.jobs.add(job);
+            if let Some(job) = self.jobs.get(id) {
+                println!("\n{}", self.jobs.format(job));
+            }
+            self.pipestatus = pipestatus;
+            return Ok(128 + Signal::SIGTSTP as i32);
+        }
+
+        if last_is_process && let JobState::Done(code) = job.state {
+            status = code;
+        }
 
-                        Err(ExecError::PermissionError) => println!("permission denied"),
-                        _ => (),
+        Ok(self.set_pipestatus(pipestatus, status))
+    }
+
+    /// Keeps the statuses of the stages of a pipeline for `PIPESTATUS` and
+    /// returns the status of the pipeline: that of the last stage or, with
+    /// `set -o pipefail`, of the last one that failed.
+    fn set_pipestatus(&mut self, pipestatus: Vec<i32>, last: i32) -> i32 {
+        let status = match self.options.pipefail {
+            true => pipestatus
+                .iter()
+                .rev()
+                .find(|&&s| s != 0)
+                .copied()
+                .unwrap_or(0),
+            false => last,
+        };
+        self.pipestatus = pipestatus;
+        status
+    }
+
+    /// Starts an external command as a stage of a pipeline, reading from
+    /// `stdin` if given and, if `piped`, writing to a pipe for the next
+    /// stage. Returns its pid and the read end of that pipe, or the status
+    /// to use if it could not be started.
+    fn spawn(
+        &self,
+        args: &[String],
+        assignments: &Assignments,
+        redirections: Redirections,
+        stdin: Option<OwnedFd>,
+        piped: bool,
+        pgid: Option<Pid>,
+    ) -> Result<(Pid, Option<OwnedFd>), i32> {
+        let Some((name, args)) = args.split_first() else {
+            return Err(0);
+        };
+        let mut cmd = process::Command::new(name);
+        cmd.args(args)
+            .env_clear()
+            .envs(self.vars.exported())
+            .envs(assignments.iter().cloned());
+
+        if let Some(stdin) = stdin {
+            cmd.stdin(stdin);
+        }
+
+        if piped {
+            cmd.stdout(Stdio::piped());
+        }
+
+        let group = pgid.map_or(0, Pid::as_raw);
+        let terminal = self.terminal.as_ref().map(AsRawFd::as_raw_fd);
+        if self.job_control {
+            cmd.process_group(group);
+        }
+
+        let redirections = Arc::new(redirections);
+        let child_redirections = Arc::clone(&redirections);
+        // SAFETY: handing over the terminal, resetting signals and
+        // applying the redirections only make system calls.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(terminal) = terminal {
+                    jobs::give_terminal(terminal, group);
+                }
+                jobs::reset_signals();
+                child_redirections.apply()
+            });
+        }
+
+        let (message, status) = match cmd.spawn() {
+            Ok(mut child) => {
+                return Ok((
+                    Pid::from_raw(child.id() as i32),
+                    child.stdout.take().map(OwnedFd::from),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                (format!("command not found: {name}"), 127)
+            }
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                (format!("permission denied: {name}"), 126)
+            }
+            Err(e) => (format!("{name}: {e}"), 1),
+        };
+        // the error goes where the command's own errors would have gone
+        let saved = redirections.apply_saved();
+        eprintln!("{message}");
+        if let Ok(saved) = saved {
+            saved.restore();
+        }
+        Err(status)
+    }
+
+    /// Runs a builtin, function or compound command in the shell.
+    fn run_stage(&mut self, stage: Stage) -> Result<i32, ExecError> {
+        match stage {
+            Stage::Command(cmd, assignments) => {
+                self.with_assignments(assignments, |shell| shell.run_builtin(cmd))
+            }
+            Stage::Compound(compound) => self.run_compound(compound),
+            Stage::Arith(expr) => self.arith_command(expr),
+            Stage::Define(name, function) => {
+                self.functions.insert(name.to_owned(), Rc::clone(function));
+                Ok(0)
+            }
+        }
+    }
+
+    /// Runs a stage in a forked copy of the shell as part of a pipeline, the
+    /// way [`Shell::spawn`] starts an external command.
+    fn fork_stage(
+        &mut self,
+        stage: Stage,
+        redirections: Redirections,
+        stdin: Option<OwnedFd>,
+        piped: bool,
+        pgid: Option<Pid>,
+    ) -> nix::Result<(Pid, Option<OwnedFd>)> {
+        let (reader, writer) = match piped {
+            true => unistd::pipe().map(|(reader, writer)| (Some(reader), Some(writer)))?,
+            false => (None, None),
+        };
+        let _ = io::stdout().flush();
+
+        // SAFETY: the shell is single threaded, so the child can keep using
+        // the allocator and the standard streams.
+        match unsafe { unistd::fork() }? {
+            ForkResult::Child => {
+                if self.job_control {
+                    let group = pgid.unwrap_or(Pid::from_raw(0));
+                    let _ = unistd::setpgid(Pid::from_raw(0), group);
+                    if let Some(terminal) = &self.terminal {
+                        jobs::give_terminal(terminal.as_raw_fd(), group.as_raw());
                     }
                 }
-                // not yes, break
-                _ => break,
-            };
+                self.enter_subshell();
+
+                // the next stage must be the only reader, or it could not
+                // see this one finish
+                drop(reader);
+                let redirected = stdin
+                    .map_or(Ok(()), unistd::dup2_stdin)
+                    .and_then(|()| writer.map_or(Ok(()), unistd::dup2_stdout));
+                if let Err(e) = redirected {
+                    eprintln!("{e}");
+                    process::exit(1);
+                }
+                if let Err(e) = redirections.apply() {
+                    eprintln!("{e}");
+                    process::exit(1);
+                }
+
+                let status = match self.run_stage(stage) {
+                    Ok(status) | Err(ExecError::Return(status) | ExecError::Exit(status)) => status,
+                    Err(_) => 0,
+                };
+                let _ = io::stdout().flush();
+                process::exit(status);
+            }
+            ForkResult::Parent { child } => {
+                if self.job_control {
+                    // also done by the child, whichever runs first
+                    let _ = unistd::setpgid(child, pgid.unwrap_or(child));
+                }
+                Ok((child, reader))
+            }
         }
     }
 
-    async fn execute(&mut self, cmds: Vec<Command>) -> Result<(), ExecError> {
-        let mut prev_stdout: Option<ChildStdout> = None;
-        let mut children = Vec::new();
+    /// Puts the shell in its own process group and takes the terminal, if
+    /// it is interactive.
+    fn init_job_control(&mut self) {
+        let stdin = io::stdin();
+        if !unistd::isatty(&stdin).unwrap_or(false) {
+            return;
+        }
 
-        for (i, cmd) in cmds.iter().enumerate() {
-            match cmd {
-                Command::Error(msg) => println!("error: {}", msg),
-                Command::Exit => return Err(ExecError::Exit),
-                Command::Cd(args) => builtins::cd(args),
-                Command::Pwd(args) => builtins::pwd(args),
-                Command::Help => builtins::help(),
-                Command::SetVar(k, v) => {
-                    self.env_vars.insert(k.to_owned(), v.to_owned());
-                }
-                Command::GetVar(args) => self.bn_get(args),
-                Command::ListVars => self.bn_lsv(),
-                Command::Wish => {
-                    println!("entering wish mode...");
-                    self.mode = ShellMode::Wish;
-                }
-                Command::External {
-                    args,
-                    input,
-                    output,
-                } => {
-                    if let Some((name, args)) = args.split_first() {
-                        let mut cmd = process::Command::new(name);
-                        cmd.args(args);
-
-                        if let Some(stdout) = prev_stdout.take() {
-                            cmd.stdin(stdout);
-                        }
+        jobs::ignore_signals();
+        let _ = unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0));
+        let _ = unistd::tcsetpgrp(&stdin, unistd::getpgrp());
 
-                        if i != cmds.len() - 1 {
-                            cmd.stdout(Stdio::piped());
-                        }
+        self.terminal = redirect::private_copy(libc::STDIN_FILENO).ok();
+        self.job_control = self.terminal.is_some();
+    }
 
-                        if let CommandIO::File(path) = input {
-                            cmd.stdin(match File::open(path) {
-                                Ok(file) => file,
-                                Err(e) => {
-                                    println!("error reading file: {}", e);
-                                    return Err(ExecError::PermissionError);
-                                }
-                            });
-                        }
+    /// Prepares a forked copy of the shell, which leaves job control and the
+    /// terminal to its parent. Signals get their default actions, so that,
+    /// like a program, it is stopped by Ctrl-Z and killed by a broken pipe.
+    fn enter_subshell(&mut self) {
+        self.job_control = false;
+        self.terminal = None;
+        // only the shell itself reads debugger commands
+        self.debugger = None;
 
-                        if let CommandIO::File(path) = output {
-                            cmd.stdout(File::create(path).expect("error creating file"));
-                        }
+        jobs::reset_signals();
+        // SAFETY: restoring the default action installs no handler.
+        unsafe {
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+    }
 
-                        match cmd.spawn() {
-                            Ok(mut child) => {
-                                prev_stdout = child.stdout.take();
-                                children.push(child);
-                            }
-                            Err(_) => return Err(ExecError::CommandNotFound(name.to_owned())),
-                        };
+    /// Takes the terminal back from a foreground job.
+    fn reclaim_terminal(&self) {
+        if let Some(terminal) = &self.terminal {
+            let _ = unistd::tcsetpgrp(terminal, unistd::getpgrp());
+        }
+    }
+
+    /// Reports jobs that finished or were stopped since the last prompt,
+    /// forgetting the finished ones.
+    fn notify_jobs(&mut self) {
+        if !self.job_control {
+            return;
+        }
+
+        self.jobs.iter_mut().for_each(Job::poll);
+        for id in self.jobs.ids() {
+            let Some(job) = self.jobs.get(id).filter(|job| job.changed) else {
+                continue;
+            };
+            println!("{}", self.jobs.format(job));
+
+            if matches!(job.state, JobState::Done(_)) {
+                self.jobs.remove(id);
+            } else if let Some(job) = self.jobs.get_mut(id) {
+                job.changed = false;
+            }
+        }
+    }
+
+    /// Runs a builtin command, returning its exit status.
+    fn run_builtin(&mut self, cmd: Command) -> Result<i32, ExecError> {
+        let mut status = 0;
+
+        match &cmd {
+            Command::Exit(args) => return self.bn_exit(args),
+            Command::Cd(args) => match builtins::cd(args, self.vars.get("HOME")) {
+                Ok(old) => {
+                    if let Some(old) = old {
+                        self.vars.set("OLDPWD", old);
+                    }
+                    if let Ok(dir) = std::env::current_dir() {
+                        self.vars.set("PWD", dir.to_string_lossy().into_owned());
                     }
                 }
-            };
+                Err(code) => status = code,
+            },
+            Command::Pwd(args) => status = builtins::pwd(args),
+            Command::Help => builtins::help(),
+            Command::GetVar(args) => status = self.bn_get(args),
+            Command::ListVars => self.bn_lsv(),
+            Command::Set(args) => status = self.bn_set(args),
+            Command::Shopt(args) => status = self.bn_shopt(args),
+            Command::Let(args) => return self.bn_let(args),
+            Command::Jobs(args) => status = self.bn_jobs(args),
+            Command::Fg(args) => status = self.bn_fg(args),
+            Command::Bg(args) => status = self.bn_bg(args),
+            Command::Wait(args) => status = self.bn_wait(args),
+            Command::Disown(args) => status = self.bn_disown(args),
+            Command::Wish => {
+                println!("entering wish mode...");
+                self.mode = ShellMode::Wish;
+            }
+            Command::Break(args) => return self.bn_loop_control("break", args, ExecError::Break),
+            Command::Continue(args) => {
+                return self.bn_loop_control("continue", args, ExecError::Continue);
+            }
+            Command::Local(args) => status = self.bn_local(args),
+            Command::Return(args) => return self.bn_return(args),
+            Command::Source(args) => return self.bn_source(args),
+            Command::Shift(args) => status = self.bn_shift(args),
+            Command::Functions(args) => status = self.bn_functions(args),
+            Command::Export(args) => status = self.bn_export(args),
+            Command::Unset(args) => status = self.bn_unset(args),
+            Command::Call(args) => return self.call_function(args),
+            Command::External(_) => unreachable!("only builtins are run here"),
+        }
+
+        Ok(status)
+    }
+
+    /// Runs a compound command in the shell, returning the status of the
+    /// last command it ran.
+    fn run_compound(&mut self, compound: &Compound) -> Result<i32, ExecError> {
+        match compound {
+            Compound::Subshell(body) | Compound::Group(body) => self.run_list(body),
+            Compound::If {
+                branches,
+                otherwise,
+            } => {
+                for (condition, body) in branches {
+                    if self.run_condition(|shell| shell.run_list(condition))? == 0 {
+                        return self.run_list(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.run_list(body),
+                    None => Ok(0),
+                }
+            }
+            Compound::While {
+                condition,
+                body,
+                until,
+            } => self.run_loop(|shell| {
+                match shell.run_condition(|shell| shell.run_iteration(condition))? {
+                    Some(status) if (status == 0) != *until => (),
+                    _ => return Ok(None),
+                }
+                shell.run_iteration(body)
+            }),
+            Compound::For { name, words, body } => {
+                let items = match words {
+                    Some(words) => match expand::expand_words(words, self) {
+                        Ok(items) => items,
+                        Err(e) => return self.expansion_failed(&e, e.is_fatal()),
+                    },
+                    None => self.positional.clone(),
+                };
+                let mut items = items.into_iter();
+                self.run_loop(|shell| {
+                    let Some(item) = items.next() else {
+                        return Ok(None);
+                    };
+                    shell.vars.set(name, item);
+                    shell.run_iteration(body)
+                })
+            }
+            Compound::Case { word, arms } => self.run_case(word, arms),
+        }
+    }
+
+    /// Runs `step` until it returns `None`, counting as a loop for `break`
+    /// and `continue`. Returns the status of the last body that ran.
+    fn run_loop(
+        &mut self,
+        mut step: impl FnMut(&mut Shell) -> Result<Option<i32>, ExecError>,
+    ) -> Result<i32, ExecError> {
+        let mut status = 0;
+        self.loop_depth += 1;
+        let result = loop {
+            match step(self) {
+                Ok(Some(last)) => status = last,
+                Ok(None) => break Ok(status),
+                Err(e) => break Err(e),
+            }
+        };
+        self.loop_depth -= 1;
+        result
+    }
+
+    /// Runs a list in a loop. Returns `None` if a `break` ends the loop; a
+    /// `break` or `continue` for an enclosing loop is passed on.
+    fn run_iteration(&mut self, list: &List) -> Result<Option<i32>, ExecError> {
+        match self.run_list(list) {
+            Ok(status) => Ok(Some(status)),
+            Err(ExecError::Break(1)) => Ok(None),
+            Err(ExecError::Continue(1)) => Ok(Some(0)),
+            Err(ExecError::Break(n)) => Err(ExecError::Break(n - 1)),
+            Err(ExecError::Continue(n)) => Err(ExecError::Continue(n - 1)),
+            Err(e) => Err(e),
         }
+    }
+
+    /// Runs the list of the first arm of a `case` with a pattern matching
+    /// the word.
+    fn run_case(&mut self, word: &Word, arms: &[CaseArm]) -> Result<i32, ExecError> {
+        let subject = match expand::expand_content(word, self) {
+            Ok(subject) => subject,
+            Err(e) => return self.expansion_failed(&e, e.is_fatal()),
+        };
 
-        // wait for commands
-        for mut child in children {
-            match child.wait() {
-                Ok(status) => {
-                    if !status.success() {
-                        println!(
-                            "command {} exited with status {:?}",
-                            child.id(),
-                            status.code().unwrap_or_default()
-                        );
+        for arm in arms {
+            for pattern in &arm.patterns {
+                match expand::expand_pattern(pattern, self) {
+                    Ok(pattern) if glob::matches(&pattern, &subject) => {
+                        return self.run_list(&arm.body);
                     }
+                    Ok(_) => (),
+                    Err(e) => return self.expansion_failed(&e, e.is_fatal()),
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    /// Resolves a command to the stage it runs as, expanding the words of a
+    /// simple command and opening redirections. Returns `None` if there is
+    /// nothing to run.
+    fn resolve<'a>(
+        &mut self,
+        cmd: &'a parser::Command,
+    ) -> Result<Option<(Stage<'a>, Redirections)>, RedirectError> {
+        Ok(match cmd {
+            parser::Command::Simple(simple) => {
+                self.expand_simple(simple)?
+                    .map(|(cmd, assignments, redirections)| {
+                        (Stage::Command(cmd, assignments), redirections)
+                    })
+            }
+            parser::Command::Compound {
+                compound,
+                redirects,
+            } => Some((Stage::Compound(compound), self.redirections(redirects)?)),
+            parser::Command::FunctionDef { name, function } => {
+                Some((Stage::Define(name, function), Redirections::default()))
+            }
+            parser::Command::Arith(expr) => Some((Stage::Arith(expr), Redirections::default())),
+        })
+    }
+
+    /// Expands the words of a simple command and opens its redirections,
+    /// resolving it to the command it names, then expands its assignments.
+    /// Without a command they set shell variables and `None` is returned;
+    /// otherwise they are returned to be given to the command alone.
+    fn expand_simple(
+        &mut self,
+        simple: &SimpleCommand,
+    ) -> Result<Option<(Command, Assignments, Redirections)>, RedirectError> {
+        let args = expand::expand_words(&simple.words, self)?;
+        let redirections = self.redirections(&simple.redirects)?;
+
+        if args.is_empty() {
+            let mut assignments = Assignments::new();
+            for assignment in &simple.assignments {
+                let (name, value) = expand::expand_assignment(assignment, self)?;
+                self.vars.set(&name, value.clone());
+                assignments.push((name, value));
+            }
+            if self.options.xtrace && !assignments.is_empty() {
+                self.trace(&assignments, &args);
+            }
+            return Ok(None);
+        }
+
+        // each assignment sees the ones before it, but only while expanding
+        let mut assignments = Assignments::new();
+        let mut saved = vec![];
+        let mut result = Ok(());
+        for assignment in &simple.assignments {
+            match expand::expand_assignment(assignment, self) {
+                Ok((name, value)) => {
+                    saved.push(self.vars.save(&name));
+                    self.vars.set(&name, value.clone());
+                    assignments.push((name, value));
                 }
                 Err(e) => {
-                    println!("error waiting for command: {}", e);
+                    result = Err(e);
+                    break;
                 }
             }
         }
+        for saved in saved.into_iter().rev() {
+            self.vars.restore(saved);
+        }
+        result?;
 
-        Ok(())
+        if self.options.xtrace {
+            self.trace(&assignments, &args);
+        }
+        Ok(Some((
+            Command::from(args, &self.functions),
+            assignments,
+            redirections,
+        )))
     }
 
-    fn expand(&self, input: &str) -> String {
-        let mut result = String::new();
-        let mut chars = input.chars().peekable();
+    /// Prints an expanded command to standard error before it runs, for
+    /// `set -x`. It follows `PS4`, itself expanded, which is `+ ` unless
+    /// set.
+    fn trace(&mut self, assignments: &Assignments, args: &[String]) {
+        let words: Vec<_> = assignments
+            .iter()
+            .map(|(name, value)| format!("{name}={}", trace_quote(value)))
+            .chain(args.iter().map(|arg| trace_quote(arg)))
+            .collect();
+        let ps4 = self.vars.get("PS4").unwrap_or("+ ").to_owned();
+        let prefix = expand::expand_string(&ps4, self).unwrap_or(ps4);
+        eprintln!("{prefix}{}", words.join(" "));
+    }
 
-        while let Some(c) = chars.next() {
-            if c == '$' {
-                let mut var_name = String::new();
+    /// Stops before a pipeline if the debugger asks to, then reads debugger
+    /// commands from standard input until one lets the script go on.
+    fn debug_stop(&mut self, pipeline: &Pipeline) -> Result<(), ExecError> {
+        let depth = self.call_stack.len();
+        if !self
+            .debugger
+            .as_ref()
+            .is_some_and(|debugger| debugger.stops_at(pipeline.line, depth))
+        {
+            return Ok(());
+        }
 
-                while let Some(&next) = chars.peek() {
-                    if next.is_whitespace() || "=$".contains(next) {
-                        break;
+        eprintln!("{}:{}: {}", self.name, pipeline.line, pipeline.text);
+        let mut input = String::new();
+        loop {
+            eprint!("(debug) ");
+            input.clear();
+            // without anyone to ask, the script runs to its end
+            if matches!(io::stdin().read_line(&mut input), Ok(0) | Err(_)) {
+                eprintln!();
+                self.debugger = None;
+                return Ok(());
+            }
+            let Some(debugger) = &mut self.debugger else {
+                return Ok(());
+            };
+
+            match debugger.parse(&input) {
+                Ok(DebugCommand::Print(text)) => match expand::expand_string(&text, self) {
+                    Ok(text) => eprintln!("{text}"),
+                    Err(e) => eprintln!("print: {e}"),
+                },
+                Ok(DebugCommand::Break(Some(line))) => {
+                    debugger.add_breakpoint(line);
+                    eprintln!("breakpoint at line {line}");
+                }
+                Ok(DebugCommand::Break(None)) => {
+                    for line in debugger.breakpoints() {
+                        eprintln!("breakpoint at line {line}");
+                    }
+                }
+                Ok(DebugCommand::Delete(line)) => {
+                    if !debugger.remove_breakpoint(line) {
+                        eprintln!("delete: no breakpoint at line {line}");
                     }
-                    var_name.push(next);
-                    chars.next();
                 }
+                Ok(DebugCommand::Help) => Debugger::help(),
+                Ok(DebugCommand::Quit) => return Err(ExecError::Exit(self.status)),
+                Ok(command) => {
+                    debugger.resume(&command, depth);
+                    return Ok(());
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+
+    /// Runs `f` with `assignments` set and exported, then puts the
+    /// variables back the way they were.
+    fn with_assignments<T>(
+        &mut self,
+        assignments: Assignments,
+        f: impl FnOnce(&mut Shell) -> T,
+    ) -> T {
+        let mut saved = vec![];
+        for (name, value) in assignments {
+            saved.push(self.vars.save(&name));
+            self.vars.set(&name, value);
+            self.vars.export(&name);
+        }
+
+        let result = f(self);
+
+        for saved in saved.into_iter().rev() {
+            self.vars.restore(saved);
+        }
+        result
+    }
+
+    /// Expands the targets of redirections and opens them.
+    fn redirections(&mut self, redirects: &[Redirect]) -> Result<Redirections, RedirectError> {
+        let mut redirections = Redirections::default();
+        for Redirect { fd, op, target } in redirects {
+            match op {
+                RedirectOp::HereDoc => {
+                    redirections.content(*fd, &expand::expand_content(target, self)?)?
+                }
+                RedirectOp::HereString => {
+                    let content = expand::expand_content(target, self)? + "\n";
+                    redirections.content(*fd, &content)?
+                }
+                RedirectOp::DupInput | RedirectOp::DupOutput => {
+                    redirections.dup(*fd, &expand::expand_redirect(target, self)?)?
+                }
+                op => {
+                    let path = expand::expand_redirect(target, self)?;
+                    redirections.open(*fd, *op, &path, self.options.noclobber)?
+                }
+            }
+        }
+
+        Ok(redirections)
+    }
+
+    /// Reports a failed expansion. If it is `fatal`, as an unset variable is
+    /// with `set -u`, a shell that is not interactive exits; otherwise the
+    /// command fails.
+    fn expansion_failed(&self, error: impl Display, fatal: bool) -> Result<i32, ExecError> {
+        eprintln!("{error}");
+        match fatal && !self.job_control {
+            true => Err(ExecError::Exit(127)),
+            false => Ok(1),
+        }
+    }
+
+    /// Runs a `((...))` command, whose expression is expanded first. Like
+    /// `let`, a script goes on after an error in the expression itself,
+    /// unless it uses a variable that is not set with `set -u`.
+    fn arith_command(&mut self, expr: &str) -> Result<i32, ExecError> {
+        let (error, fatal) = match expand::expand_string(expr, self) {
+            Ok(expr) => match self.arith(&expr) {
+                Ok(value) => return Ok((value == 0) as i32),
+                Err(e) => {
+                    let fatal = matches!(e, ArithError::Unbound(_));
+                    (ExpandError::from(e), fatal)
+                }
+            },
+            Err(e) => {
+                let fatal = e.is_fatal();
+                (e, fatal)
+            }
+        };
+        self.expansion_failed(format!("((: {error}"), fatal)
+    }
+
+    fn arith(&mut self, expr: &str) -> Result<i64, ArithError> {
+        arith::eval(expr, self)
+    }
+
+    /// Runs `command` in a forked copy of the shell and returns its standard
+    /// output without trailing newlines.
+    fn command_substitution(&mut self, command: &str) -> String {
+        let (reader, writer) = match unistd::pipe() {
+            Ok(fds) => fds,
+            Err(e) => {
+                eprintln!("command substitution: {e}");
+                return String::new();
+            }
+        };
+
+        // anything still buffered would otherwise be written twice
+        let _ = io::stdout().flush();
+
+        // SAFETY: the shell is single threaded, so the child can keep using
+        // the allocator and the standard streams.
+        match unsafe { unistd::fork() } {
+            Ok(ForkResult::Child) => {
+                drop(reader);
+                if unistd::dup2_stdout(&writer).is_err() {
+                    process::exit(1);
+                }
+                drop(writer);
+                self.enter_subshell();
+
+                let status = match self.eval(command) {
+                    Ok(status) | Err(ExecError::Exit(status)) => status,
+                    Err(_) => 0,
+                };
+                let _ = io::stdout().flush();
+                process::exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                drop(writer);
+                let mut output = vec![];
+                if let Err(e) = File::from(reader).read_to_end(&mut output) {
+                    eprintln!("command substitution: {e}");
+                }
+                self.substitution_status = Some(match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, code)) => code,
+                    Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+                    _ => 1,
+                });
+
+                String::from_utf8_lossy(&output)
+                    .trim_end_matches('\n')
+                    .to_owned()
+            }
+            Err(e) => {
+                eprintln!("command substitution: {e}");
+                String::new()
+            }
+        }
+    }
 
-                let val = self.get_var(&var_name);
-                result.push_str(&val);
+    /// Lists all jobs or the named ones. `-l` adds their process group ids
+    /// and `-p` prints only those. Finished jobs are forgotten once listed.
+    fn bn_jobs(&mut self, args: &[String]) -> i32 {
+        let mut long = false;
+        let mut pids_only = false;
+        let mut specs = vec![];
+        for arg in &args[1..] {
+            match arg.as_str() {
+                "-l" => long = true,
+                "-p" => pids_only = true,
+                opt if opt.starts_with('-') => {
+                    eprintln!("jobs: {opt}: invalid option");
+                    return 2;
+                }
+                _ => specs.push(arg.clone()),
+            }
+        }
+
+        self.jobs.iter_mut().for_each(Job::poll);
+        let ids = if specs.is_empty() {
+            self.jobs.ids()
+        } else {
+            match self.job_ids("jobs", &specs) {
+                Some(ids) => ids,
+                None => return 1,
+            }
+        };
+
+        for id in ids {
+            let Some(job) = self.jobs.get(id) else {
+                continue;
+            };
+            if pids_only {
+                println!("{}", job.pgid);
+            } else if long {
+                println!("{}", self.jobs.format_long(job));
             } else {
-                result.push(c)
+                println!("{}", self.jobs.format(job));
+            }
+
+            if matches!(job.state, JobState::Done(_)) {
+                self.jobs.remove(id);
+            } else if let Some(job) = self.jobs.get_mut(id) {
+                job.changed = false;
             }
         }
 
-        result
+        0
+    }
+
+    /// Continues a job in the foreground, the current one by default, and
+    /// waits for it.
+    fn bn_fg(&mut self, args: &[String]) -> i32 {
+        if !self.job_control {
+            eprintln!("fg: no job control");
+            return 1;
+        }
+        let Some(ids) = self.job_ids("fg", &args[1..args.len().min(2)]) else {
+            return 1;
+        };
+        let Some(mut job) = self.jobs.remove(ids[0]) else {
+            return 1;
+        };
+
+        println!("{}", job.command);
+        if let Some(terminal) = &self.terminal {
+            let _ = unistd::tcsetpgrp(terminal, job.pgid);
+        }
+        if let Err(e) = killpg(job.pgid, Signal::SIGCONT) {
+            eprintln!("fg: {e}");
+        }
+        job.state = JobState::Running;
+        job.wait(true);
+        self.reclaim_terminal();
+
+        match job.state {
+            JobState::Done(status) => status,
+            _ => {
+                let id = job.id;
+                job.changed = false;
+                self.jobs.insert(job);
+                if let Some(job) = self.jobs.get(id) {
+                    println!("\n{}", self.jobs.format(job));
+                }
+                128 + Signal::SIGTSTP as i32
+            }
+        }
+    }
+
+    /// Continues stopped jobs in the background, the current one by default.
+    fn bn_bg(&mut self, args: &[String]) -> i32 {
+        if !self.job_control {
+            eprintln!("bg: no job control");
+            return 1;
+        }
+        let Some(ids) = self.job_ids("bg", &args[1..]) else {
+            return 1;
+        };
+
+        let mut status = 0;
+        for id in ids {
+            let Some(job) = self.jobs.get_mut(id) else {
+                continue;
+            };
+            if let Err(e) = killpg(job.pgid, Signal::SIGCONT) {
+                eprintln!("bg: {e}");
+                status = 1;
+                continue;
+            }
+            job.state = JobState::Running;
+            job.changed = false;
+            println!("[{id}] {}", job.display_command());
+        }
+        status
+    }
+
+    /// Waits for the named jobs or processes, or for every job, returning
+    /// the status of the last one named.
+    fn bn_wait(&mut self, args: &[String]) -> i32 {
+        if args.len() < 2 {
+            for id in self.jobs.ids() {
+                self.wait_job(id);
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in &args[1..] {
+            let id = if arg.starts_with('%') {
+                self.jobs.find(arg).map_err(|e| e.to_string())
+            } else {
+                match arg.parse() {
+                    Ok(pid) => self
+                        .jobs
+                        .find_pid(Pid::from_raw(pid))
+                        .ok_or_else(|| format!("pid {arg} is not a child of this shell")),
+                    Err(_) => Err(format!("{arg}: not a pid or valid job spec")),
+                }
+            };
+            status = match id {
+                Ok(id) => self.wait_job(id),
+                Err(e) => {
+                    eprintln!("wait: {e}");
+                    127
+                }
+            };
+        }
+        status
+    }
+
+    /// Waits for a job to finish, forgetting it, or, with job control, to be
+    /// stopped.
+    fn wait_job(&mut self, id: usize) -> i32 {
+        let Some(mut job) = self.jobs.remove(id) else {
+            return 127;
+        };
+        job.wait(self.job_control);
+
+        match job.state {
+            JobState::Done(status) => status,
+            _ => {
+                self.jobs.insert(job);
+                128 + Signal::SIGTSTP as i32
+            }
+        }
+    }
+
+    /// Removes jobs from the table, the current one by default or all of
+    /// them with `-a`, so the shell no longer reports them.
+    fn bn_disown(&mut self, args: &[String]) -> i32 {
+        let ids = match args.get(1).map(String::as_str) {
+            Some("-a") => self.jobs.ids(),
+            _ => match self.job_ids("disown", &args[1..]) {
+                Some(ids) => ids,
+                None => return 1,
+            },
+        };
+        for id in ids {
+            self.jobs.remove(id);
+        }
+        0
+    }
+
+    /// Resolves job specs for the builtin `name`, with no specs meaning the
+    /// current job. Reports an unknown job and returns `None`.
+    fn job_ids(&self, name: &str, specs: &[String]) -> Option<Vec<usize>> {
+        let result: Result<Vec<_>, JobError> = if specs.is_empty() {
+            self.jobs.find("%+").map(|id| vec![id])
+        } else {
+            specs.iter().map(|spec| self.jobs.find(spec)).collect()
+        };
+
+        result.inspect_err(|e| eprintln!("{name}: {e}")).ok()
+    }
+
+    /// Runs a function with `args` as its positional parameters. Variables
+    /// it declares `local` get their old values back when it returns.
+    fn call_function(&mut self, args: &[String]) -> Result<i32, ExecError> {
+        let Some(function) = self.functions.get(&args[0]).cloned() else {
+            return Ok(127);
+        };
+        if self.locals.len() >= MAX_FUNCTION_DEPTH {
+            eprintln!(
+                "{}: maximum function nesting level exceeded ({MAX_FUNCTION_DEPTH})",
+                args[0]
+            );
+            return Ok(1);
+        }
+
+        let positional = mem::replace(&mut self.positional, args[1..].to_vec());
+        // loops around the call are out of reach of `break` inside it
+        let loop_depth = mem::take(&mut self.loop_depth);
+        self.locals.push(HashMap::new());
+        self.call_stack.push(args[0].clone());
+
+        let result = self.execute(slice::from_ref(&function.body), &function.text);
+
+        self.call_stack.pop();
+        for (name, value) in self.locals.pop().unwrap_or_default() {
+            match value {
+                Some(value) => self.vars.set(&name, value),
+                None => {
+                    self.vars.unset(&name);
+                }
+            }
+        }
+        self.positional = positional;
+        self.loop_depth = loop_depth;
+
+        match result {
+            Err(ExecError::Return(status)) => Ok(status),
+            result => result,
+        }
     }
 
-    fn bn_get(&self, args: &[String]) {
+    /// Declares variables local to the running function, optionally giving
+    /// them values with `name=value`.
+    fn bn_local(&mut self, args: &[String]) -> i32 {
+        let Some(frame) = self.locals.last_mut() else {
+            eprintln!("local: can only be used in a function");
+            return 1;
+        };
+
+        let mut status = 0;
+        for arg in &args[1..] {
+            let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+            if !expand::is_name(name) {
+                eprintln!("local: `{arg}': not a valid identifier");
+                status = 1;
+                continue;
+            }
+            if !frame.contains_key(name) {
+                frame.insert(name.to_owned(), self.vars.get(name).map(str::to_owned));
+            }
+            self.vars.set(name, value.to_owned());
+        }
+        status
+    }
+
+    /// Returns from the running function with status `n`, by default that
+    /// of the last command.
+    fn bn_return(&self, args: &[String]) -> Result<i32, ExecError> {
+        if self.locals.is_empty() && self.sourcing == 0 {
+            eprintln!("return: can only `return' from a function or sourced script");
+            return Ok(1);
+        }
+        match args.get(1).map(|n| n.parse::<i32>()) {
+            None => Err(ExecError::Return(self.status)),
+            Some(Ok(n)) => Err(ExecError::Return(n & 0xff)),
+            Some(Err(_)) => {
+                eprintln!("return: {}: numeric argument required", args[1]);
+                Err(ExecError::Return(2))
+            }
+        }
+    }
+
+    /// Runs the commands of a file in the shell itself. Further arguments
+    /// become the positional parameters while it runs. A name without a
+    /// `/` is looked up in `PATH` first.
+    fn bn_source(&mut self, args: &[String]) -> Result<i32, ExecError> {
+        let Some(name) = args.get(1) else {
+            eprintln!("{}: filename argument required", args[0]);
+            return Ok(2);
+        };
+        let file = match File::open(find_script(name, self.vars.get("PATH"))) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("{}: {name}: {e}", args[0]);
+                return Ok(1);
+            }
+        };
+
+        let positional =
+            (args.len() > 2).then(|| mem::replace(&mut self.positional, args[2..].to_vec()));
+        self.sourcing += 1;
+
+        let result = self.run_script(BufReader::new(file));
+
+        self.sourcing -= 1;
+        if let Some(positional) = positional {
+            self.positional = positional;
+        }
+
+        match result {
+            Err(ExecError::Return(status)) => Ok(status),
+            result => result,
+        }
+    }
+
+    /// Prints the definitions of all functions, or of the named ones.
+    fn bn_functions(&self, args: &[String]) -> i32 {
+        if args.len() < 2 {
+            let mut names: Vec<_> = self.functions.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{}", self.functions[name].text);
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for name in &args[1..] {
+            match self.functions.get(name) {
+                Some(function) => println!("{}", function.text),
+                None => {
+                    eprintln!("functions: {name}: not found");
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    /// Runs `break [n]` or `continue [n]`, which unwind `n` loops, or all of
+    /// them if there are fewer.
+    fn bn_loop_control(
+        &self,
+        name: &str,
+        args: &[String],
+        control: fn(usize) -> ExecError,
+    ) -> Result<i32, ExecError> {
+        let levels = match args.get(1).map(|n| n.parse::<usize>()) {
+            None => 1,
+            Some(Ok(n @ 1..)) => n,
+            Some(_) => {
+                eprintln!("{name}: {}: loop count out of range", args[1]);
+                return Ok(1);
+            }
+        };
+        if self.loop_depth == 0 {
+            eprintln!("{name}: only meaningful in a `for', `while', or `until' loop");
+            return Ok(0);
+        }
+        Err(control(levels.min(self.loop_depth)))
+    }
+
+    /// Exports variables, giving them values with `name=value`. With `-n`
+    /// they stop being exported instead. Without names, or with `-p`, lists
+    /// the exported variables in a form that can be read back.
+    fn bn_export(&mut self, args: &[String]) -> i32 {
+        let mut unexport = false;
+        let mut names = &args[1..];
+        while let Some(flag) = names.first().filter(|arg| arg.starts_with('-')) {
+            names = &names[1..];
+            match flag.as_str() {
+                "-p" => (),
+                "-n" => unexport = true,
+                "--" => break,
+                _ => {
+                    eprintln!("export: {flag}: invalid option");
+                    return 2;
+                }
+            }
+        }
+
+        if names.is_empty() {
+            for (name, value) in self.vars.exported() {
+                println!("export {name}={}", quote(value));
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in names {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !expand::is_name(name) {
+                eprintln!("export: `{arg}': not a valid identifier");
+                status = 1;
+                continue;
+            }
+            if let Some(value) = value {
+                self.vars.set(name, value.to_owned());
+            }
+            match unexport {
+                true => self.vars.unexport(name),
+                false => self.vars.export(name),
+            }
+        }
+        status
+    }
+
+    /// Removes variables, or with `-f` functions.
+    fn bn_unset(&mut self, args: &[String]) -> i32 {
+        let mut functions = false;
+        let mut names = &args[1..];
+        while let Some(flag) = names.first().filter(|arg| arg.starts_with('-')) {
+            names = &names[1..];
+            match flag.as_str() {
+                "-f" => functions = true,
+                "-v" => functions = false,
+                "--" => break,
+                _ => {
+                    eprintln!("unset: {flag}: invalid option");
+                    return 2;
+                }
+            }
+        }
+
+        let mut status = 0;
+        for name in names {
+            if functions {
+                self.functions.remove(name);
+            } else if expand::is_name(name) {
+                self.vars.unset(name);
+            } else {
+                eprintln!("unset: `{name}': not a valid identifier");
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn bn_get(&self, args: &[String]) -> i32 {
         match args.len() {
-            1 => println!("get: expected key"),
+            1 => eprintln!("get: expected key"),
             2 => {
                 let key = &args[1];
-                println!("{}", self.get_var(key))
+                println!("{}", self.get_var(key));
+                return 0;
             }
-            _ => print!("get: too many arguments"),
+            _ => eprintln!("get: too many arguments"),
         }
+        1
     }
 
     fn bn_lsv(&self) {
-        println!("{} items:", self.env_vars.len());
-        for (k, v) in &self.env_vars {
+        println!("{} items:", self.vars.len());
+        for (k, v) in self.vars.list() {
             println!("{k}: {v}");
         }
     }
 
+    /// Evaluates each argument as an arithmetic expression. The status is 0
+    /// if the last one is non-zero.
+    fn bn_let(&mut self, args: &[String]) -> Result<i32, ExecError> {
+        if args.len() < 2 {
+            eprintln!("let: expression expected");
+            return Ok(1);
+        }
+
+        let mut last = 0;
+        for expr in &args[1..] {
+            match self.arith(expr) {
+                Ok(value) => last = value,
+                Err(e) => {
+                    let fatal = matches!(e, ArithError::Unbound(_));
+                    return self.expansion_failed(format!("let: {expr}: {e}"), fatal);
+                }
+            }
+        }
+
+        Ok((last == 0) as i32)
+    }
+
+    /// Sets or unsets shell options by letter, as in `-eu` and `+x`, or by
+    /// name with `-o name`/`+o name`. Without arguments, or with a lone
+    /// `-o`, lists them; a lone `+o` lists them as `set` commands. Arguments
+    /// after the options, or after `--`, become the positional parameters.
+    fn bn_set(&mut self, args: &[String]) -> i32 {
+        let mut args = args[1..].iter();
+
+        if args.len() == 0 {
+            self.list_options(false);
+            return 0;
+        }
+
+        while let Some(arg) = args.next() {
+            let value = arg.starts_with('-');
+            match arg.as_str() {
+                "--" => {
+                    self.positional = args.cloned().collect();
+                    break;
+                }
+                _ if !arg.starts_with(['-', '+']) => {
+                    self.positional = std::iter::once(arg).chain(args).cloned().collect();
+                    break;
+                }
+                "-o" | "+o" => match args.next() {
+                    Some(name) => match self.options.get_mut(name) {
+                        Some(option) => *option = value,
+                        None => {
+                            eprintln!("set: {name}: invalid option name");
+                            return 1;
+                        }
+                    },
+                    None => self.list_options(!value),
+                },
+                _ => {
+                    for letter in arg[1..].chars() {
+                        match Options::name(letter).and_then(|name| self.options.get_mut(name)) {
+                            Some(option) => *option = value,
+                            None => {
+                                eprintln!("set: {}{letter}: invalid option", &arg[..1]);
+                                return 2;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Prints the shell options and whether they are on, or with
+    /// `as_commands` the `set` commands that turn them on or off.
+    fn list_options(&self, as_commands: bool) {
+        for (name, value) in self.options.list() {
+            match as_commands {
+                true => println!("set {}o {name}", if value { '-' } else { '+' }),
+                false => println!("{name}\t{}", if value { "on" } else { "off" }),
+            }
+        }
+    }
+
+    /// Leaves the shell with status `n`, by default that of the last
+    /// command.
+    fn bn_exit(&self, args: &[String]) -> Result<i32, ExecError> {
+        match args.get(1).map(|n| n.parse::<i32>()) {
+            None => Err(ExecError::Exit(self.status)),
+            Some(Ok(n)) => Err(ExecError::Exit(n & 0xff)),
+            Some(Err(_)) => {
+                eprintln!("exit: {}: numeric argument required", args[1]);
+                Err(ExecError::Exit(2))
+            }
+        }
+    }
+
+    /// Drops the first `n` positional parameters, 1 by default.
+    fn bn_shift(&mut self, args: &[String]) -> i32 {
+        let n = match args.get(1).map(|n| n.parse::<usize>()) {
+            None => 1,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => {
+                eprintln!("shift: {}: numeric argument required", args[1]);
+                return 2;
+            }
+        };
+        if n > self.positional.len() {
+            eprintln!("shift: {n}: shift count out of range");
+            return 1;
+        }
+        self.positional.drain(..n);
+        0
+    }
+
+    fn bn_shopt(&mut self, args: &[String]) -> i32 {
+        let (set, names) = match args.get(1).map(String::as_str) {
+            Some("-s") => (Some(true), &args[2..]),
+            Some("-u") => (Some(false), &args[2..]),
+            _ => (None, &args[1..]),
+        };
+
+        if names.is_empty() {
+            for (name, value) in self.glob_options.list() {
+                if set.is_none_or(|set| set == value) {
+                    println!("{name}\t{}", if value { "on" } else { "off" });
+                }
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for name in names {
+            match self.glob_options.get_mut(name) {
+                Some(option) => match set {
+                    Some(value) => *option = value,
+                    None => println!("{name}\t{}", if *option { "on" } else { "off" }),
+                },
+                None => {
+                    eprintln!("shopt: {name}: invalid shell option name");
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
     fn get_var(&self, key: &str) -> String {
-        if let Some(v) = self.env_vars.get(key) {
-            return v.to_owned();
+        self.var(key).unwrap_or_default()
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        if let Ok(n @ 1..) = key.parse::<usize>() {
+            return self.positional.get(n - 1).cloned();
+        }
+        match key {
+            "0" => return Some(self.name.clone()),
+            "@" => return Some(self.positional.join(" ")),
+            "*" => return Some(self.positional.join(&self.ifs_separator())),
+            "#" => return Some(self.positional.len().to_string()),
+            "?" => return Some(self.status.to_string()),
+            "$" => return Some(self.pid.to_string()),
+            "!" => return self.last_background.map(|pid| pid.to_string()),
+            "-" => return Some(self.option_flags()),
+            "PIPESTATUS" => return Some(self.pipestatus[0].to_string()),
+            "LINENO" => return Some(self.lineno.to_string()),
+            "FUNCNAME" => return self.call_stack.last().cloned(),
+            _ => (),
+        }
+        // `name[n]`, of which only `PIPESTATUS` has more than one
+        if let Some((name, index)) = key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+            return match (name, index) {
+                ("PIPESTATUS", "@") => self.var_fields(key).map(|fields| fields.join(" ")),
+                ("PIPESTATUS", "*") => {
+                    let fields = self.var_fields("PIPESTATUS[@]")?;
+                    Some(fields.join(&self.ifs_separator()))
+                }
+                ("PIPESTATUS", n) => n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| self.pipestatus.get(n))
+                    .map(i32::to_string),
+                (name, "0" | "@" | "*") => self.var(name),
+                _ => None,
+            };
+        }
+        self.vars.get(key).map(str::to_owned)
+    }
+
+    /// The values `key` stands for as separate fields, which `"$@"` and
+    /// `"${PIPESTATUS[@]}"` expand to.
+    fn var_fields(&self, key: &str) -> Option<Vec<String>> {
+        match key {
+            "@" => Some(self.positional.clone()),
+            "PIPESTATUS[@]" => Some(self.pipestatus.iter().map(i32::to_string).collect()),
+            _ => None,
+        }
+    }
+
+    /// What `$*` joins the positional parameters with: the first character
+    /// of `IFS`, a space if it is unset.
+    fn ifs_separator(&self) -> String {
+        match self.vars.get("IFS") {
+            Some(ifs) => ifs.chars().take(1).collect(),
+            None => " ".to_owned(),
+        }
+    }
+
+    /// The letters of the options in effect, for `$-`.
+    fn option_flags(&self) -> String {
+        let mut flags = self.options.flags();
+        if self.job_control {
+            flags.push('i');
+            flags.push('m');
+        }
+        flags
+    }
+}
+
+impl expand::Environment for Shell {
+    fn set_var(&mut self, name: &str, value: String) {
+        self.vars.set(name, value);
+    }
+
+    fn command_substitution(&mut self, command: &str) -> String {
+        Shell::command_substitution(self, command)
+    }
+
+    fn glob_options(&self) -> GlobOptions {
+        self.glob_options
+    }
+
+    fn fields(&self, name: &str) -> Option<Vec<String>> {
+        self.var_fields(name)
+    }
+}
+
+impl arith::Variables for Shell {
+    fn get(&self, name: &str) -> Option<String> {
+        self.var(name)
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.vars.set(name, value.to_string());
+    }
+
+    fn nounset(&self) -> bool {
+        self.options.nounset
+    }
+}
+
+/// Reads a line of a script, followed by more lines for as long as the
+/// input is incomplete. Returns `None` at the end of the script.
+fn read_complete(reader: &mut impl BufRead) -> Option<String> {
+    let mut input = String::new();
+
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) if input.is_empty() => return None,
+            // the scanner reports what is missing
+            Ok(0) | Err(_) => return Some(input),
+            Ok(_) => (),
+        }
+        if !input.is_empty() {
+            input.push('\n');
         }
-        // check env vars
-        match std::env::var(key) {
-            Ok(val) => val,
-            Err(_) => "".to_owned(),
+        input.push_str(line.strip_suffix('\n').unwrap_or(&line));
+
+        if !parser::is_incomplete(&input) {
+            return Some(input);
         }
     }
 }
+
+/// Standard input as a script. It is read a byte at a time, so the commands
+/// in it that read standard input get the lines after their own, not what
+/// a buffer took ahead of them.
+fn script_stdin() -> io::Result<BufReader<File>> {
+    let stdin = io::stdin().as_fd().try_clone_to_owned()?;
+    Ok(BufReader::with_capacity(1, File::from(stdin)))
+}
+
+/// Where `source` finds a script: a name without a `/` is looked up in
+/// `path`, falling back to the current directory.
+fn find_script(name: &str, path: Option<&str>) -> PathBuf {
+    if !name.contains('/')
+        && let Some(path) = path
+        && let Some(found) = path
+            .split(':')
+            .map(|dir| Path::new(dir).join(name))
+            .find(|candidate| candidate.is_file())
+    {
+        return found;
+    }
+    PathBuf::from(name)
+}
+
+/// Quotes `text` so that it reads back as a single word.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Quotes `text` for `set -x` output, only if it needs it.
+fn trace_quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:,+%@=".contains(c);
+    match !text.is_empty() && text.chars().all(plain) {
+        true => text.to_owned(),
+        false => quote(text),
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+
+/// A variable as it was, to be put back with [`Variables::restore`].
+#[derive(Debug)]
+pub struct Saved {
+    name: String,
+    value: Option<String>,
+    exported: bool,
+}
+
+/// The shell's variables. Exported ones make up the environment of the
+/// commands it runs; the others are only seen by the shell itself.
+#[derive(Debug, Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+    /// Names to pass on, which may not have a value yet.
+    exported: HashSet<String>,
+}
+
+impl Variables {
+    /// Starts out with the environment the shell was run with, all of it
+    /// exported. Variables that are not valid UTF-8 are left out.
+    pub fn from_env() -> Variables {
+        let mut vars = Variables::default();
+        for (name, value) in env::vars_os() {
+            if let (Ok(name), Ok(value)) = (name.into_string(), value.into_string()) {
+                vars.exported.insert(name.clone());
+                vars.values.insert(name, value);
+            }
+        }
+        vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Sets a variable, which stays exported if it was.
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    /// Removes a variable along with its export.
+    pub fn unset(&mut self, name: &str) -> Option<String> {
+        self.exported.remove(name);
+        self.values.remove(name)
+    }
+
+    pub fn export(&mut self, name: &str) {
+        self.exported.insert(name.to_owned());
+    }
+
+    /// Stops passing a variable on, keeping its value.
+    pub fn unexport(&mut self, name: &str) {
+        self.exported.remove(name);
+    }
+
+    /// Takes note of a variable, to restore it after a temporary change.
+    pub fn save(&self, name: &str) -> Saved {
+        Saved {
+            name: name.to_owned(),
+            value: self.values.get(name).cloned(),
+            exported: self.is_exported(name),
+        }
+    }
+
+    pub fn restore(&mut self, saved: Saved) {
+        let Saved {
+            name,
+            value,
+            exported,
+        } = saved;
+        match value {
+            Some(value) => self.values.insert(name.clone(), value),
+            None => self.values.remove(&name),
+        };
+        match exported {
+            true => self.exported.insert(name),
+            false => self.exported.remove(&name),
+        };
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// All variables with a value, sorted by name.
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        let mut vars: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        vars.sort_unstable();
+        vars
+    }
+
+    /// The exported variables with a value, sorted by name, which is the
+    /// environment of a command.
+    pub fn exported(&self) -> Vec<(&str, &str)> {
+        let mut vars = self.list();
+        vars.retain(|(name, _)| self.exported.contains(*name));
+        vars
+    }
+}
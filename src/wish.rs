@@ -119,8 +119,8 @@ impl CmdGen {
         let response: Response = serde_json::from_str(assistant_res.as_str())?;
 
         match response.status.as_str() {
-            "ok" => return Ok(response.commands),
-            "error" => return Err(response.error_msg.into()),
+            "ok" => Ok(response.commands),
+            "error" => Err(response.error_msg.into()),
             _ => unimplemented!(),
         }
     }
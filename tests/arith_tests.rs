@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use wsh::arith::{self, ArithError, Variables};
+
+#[derive(Default)]
+struct Vars(HashMap<String, String>);
+
+impl Variables for Vars {
+    fn get(&self, name: &str) -> Option<String> {
+        self.0.get(name).cloned()
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.0.insert(name.to_owned(), value.to_string());
+    }
+
+    fn nounset(&self) -> bool {
+        false
+    }
+}
+
+fn eval(expr: &str) -> Result<i64, ArithError> {
+    arith::eval(expr, &mut Vars::default())
+}
+
+#[test]
+fn test_operators() {
+    assert_eq!(eval("1 + 2 * 3"), Ok(7));
+    assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+    assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+    assert_eq!(eval("-2 ** 2"), Ok(4));
+    assert_eq!(eval("7 / 2 + 7 % 2"), Ok(4));
+    assert_eq!(eval("1 < 2 && 2 <= 2 && 3 != 4"), Ok(1));
+    assert_eq!(eval("!5 || 0"), Ok(0));
+    assert_eq!(eval("~0 ^ 5 & 3 | 8"), Ok(-1 ^ (5 & 3) | 8));
+    assert_eq!(eval("1 << 3 >> 1"), Ok(4));
+    assert_eq!(eval("0 ? 1 : 2 ? 3 : 4"), Ok(3));
+    assert_eq!(eval("1, 2, 3"), Ok(3));
+    assert_eq!(eval("0x10 + 010 + 2#11 + 36#z"), Ok(16 + 8 + 3 + 35));
+    assert_eq!(eval(""), Ok(0));
+}
+
+#[test]
+fn test_variables() {
+    let mut vars = Vars::default();
+    vars.set("i", 5);
+    vars.0.insert("expr".to_owned(), "i * 2".to_owned());
+
+    assert_eq!(arith::eval("i += 2", &mut vars), Ok(7));
+    assert_eq!(arith::eval("i++", &mut vars), Ok(7));
+    assert_eq!(arith::eval("++i", &mut vars), Ok(9));
+    assert_eq!(arith::eval("$i - unset", &mut vars), Ok(9));
+    assert_eq!(arith::eval("expr + 1", &mut vars), Ok(19));
+    assert_eq!(arith::eval("a = b = 3", &mut vars), Ok(3));
+    assert_eq!(vars.get("a").as_deref(), Some("3"));
+    assert_eq!(vars.get("b").as_deref(), Some("3"));
+
+    // short circuiting skips the assignment
+    assert_eq!(arith::eval("0 && (c = 1)", &mut vars), Ok(0));
+    assert_eq!(vars.get("c"), None);
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(eval("1 / 0"), Err(ArithError::DivisionByZero));
+    assert_eq!(eval("2 ** -1"), Err(ArithError::NegativeExponent));
+    assert!(matches!(eval("1 +"), Err(ArithError::Syntax(_))));
+    assert!(matches!(eval("(1"), Err(ArithError::Syntax(_))));
+    assert!(matches!(eval("1 = 2"), Err(ArithError::Syntax(_))));
+    assert!(matches!(eval("09"), Err(ArithError::Syntax(_))));
+
+    let mut vars = Vars::default();
+    vars.0.insert("loop".to_owned(), "loop".to_owned());
+    assert_eq!(arith::eval("loop", &mut vars), Err(ArithError::Recursion));
+}
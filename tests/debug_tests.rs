@@ -0,0 +1,45 @@
+use wsh::debug::{DebugCommand, Debugger};
+
+#[test]
+fn test_debug_commands() {
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.parse("s"), Ok(DebugCommand::Step));
+    assert_eq!(debugger.parse("next\n"), Ok(DebugCommand::Next));
+    // an empty line repeats the last command
+    assert_eq!(debugger.parse(""), Ok(DebugCommand::Next));
+    assert_eq!(
+        debugger.parse("print $x and $y"),
+        Ok(DebugCommand::Print("$x and $y".to_owned()))
+    );
+    assert_eq!(debugger.parse("b 12"), Ok(DebugCommand::Break(Some(12))));
+    assert_eq!(debugger.parse(""), Ok(DebugCommand::Next));
+    assert_eq!(debugger.parse("break"), Ok(DebugCommand::Break(None)));
+    assert_eq!(debugger.parse("d 3"), Ok(DebugCommand::Delete(3)));
+    assert_eq!(
+        debugger.parse("b x"),
+        Err("b: x: not a line number".to_owned())
+    );
+    assert!(debugger.parse("frobnicate").is_err());
+}
+
+#[test]
+fn test_debug_stops() {
+    let mut debugger = Debugger::new();
+    assert!(debugger.stops_at(1, 0), "stops before the first command");
+
+    debugger.resume(&DebugCommand::Next, 1);
+    assert!(!debugger.stops_at(5, 2), "steps over calls");
+    assert!(debugger.stops_at(6, 1));
+    assert!(debugger.stops_at(7, 0));
+
+    debugger.add_breakpoint(9);
+    debugger.resume(&DebugCommand::Continue, 0);
+    assert!(!debugger.stops_at(8, 0));
+    assert!(debugger.stops_at(9, 3));
+    assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [9]);
+    assert!(debugger.remove_breakpoint(9));
+    assert!(!debugger.stops_at(9, 0));
+
+    debugger.resume(&DebugCommand::Step, 0);
+    assert!(debugger.stops_at(10, 4));
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use wsh::{
+    arith::{self, ArithError},
+    brace,
+    expand::{self, Environment, ExpandError, expand_tilde, is_assignment},
+    glob::GlobOptions,
+    scanner::{Scanner, TokenKind, Word},
+};
+
+fn word(input: &str) -> Word {
+    match Scanner::new(input).scan_tokens().unwrap().remove(0).kind {
+        TokenKind::Literal(word) => word,
+        kind => panic!("expected a word, got {kind:?}"),
+    }
+}
+
+fn lookup(name: &str) -> Option<String> {
+    match name {
+        "HOME" => Some("/home/me".to_owned()),
+        "PWD" => Some("/work".to_owned()),
+        "OLDPWD" => Some("/old".to_owned()),
+        _ => None,
+    }
+}
+
+fn tilde(input: &str) -> String {
+    let word = word(input);
+    let assignment = is_assignment(&word);
+    expand_tilde(word, assignment, &lookup).text()
+}
+
+#[test]
+fn test_tilde() {
+    assert_eq!(tilde("~"), "/home/me");
+    assert_eq!(tilde("~/projects"), "/home/me/projects");
+    assert_eq!(tilde("~+/x"), "/work/x");
+    assert_eq!(tilde("~-"), "/old");
+    let root = nix::unistd::User::from_name("root").unwrap().unwrap().dir;
+    assert_eq!(tilde("~root"), root.to_string_lossy());
+    assert_eq!(tilde("~no-such-user-here/x"), "~no-such-user-here/x");
+
+    // only at the start of a word, and never when quoted
+    assert_eq!(tilde("a~"), "a~");
+    assert_eq!(tilde("'~'"), "~");
+    assert_eq!(tilde("\\~"), "~");
+    assert_eq!(tilde("~\"/x\""), "~/x");
+
+    // after `=` and `:` in assignments
+    assert_eq!(
+        tilde("PATH=~/bin:~+/bin:/usr/bin"),
+        "PATH=/home/me/bin:/work/bin:/usr/bin"
+    );
+    assert_eq!(tilde("--opt=~/x"), "--opt=~/x");
+}
+
+#[test]
+fn test_is_assignment() {
+    assert!(is_assignment(&word("foo=bar")));
+    assert!(is_assignment(&word("_x1=")));
+    assert!(!is_assignment(&word("=foo")));
+    assert!(!is_assignment(&word("1x=2")));
+    assert!(!is_assignment(&word("'a'=b")));
+}
+
+fn braces(input: &str) -> Vec<String> {
+    brace::expand(&word(input)).iter().map(Word::text).collect()
+}
+
+#[test]
+fn test_brace() {
+    assert_eq!(braces("file.{rs,toml}"), ["file.rs", "file.toml"]);
+    assert_eq!(braces("img{1..4}"), ["img1", "img2", "img3", "img4"]);
+    assert_eq!(braces("{3..1}"), ["3", "2", "1"]);
+    assert_eq!(braces("{a..i..3}"), ["a", "d", "g"]);
+    assert_eq!(braces("{01..03}"), ["01", "02", "03"]);
+    assert_eq!(braces("{1..10..4}"), ["1", "5", "9"]);
+    assert_eq!(braces("{-1..1}"), ["-1", "0", "1"]);
+    assert_eq!(braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+    assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    assert_eq!(braces("x{,.bak}"), ["x", "x.bak"]);
+
+    // not brace expansions
+    assert_eq!(braces("{a}"), ["{a}"]);
+    assert_eq!(braces("{}"), ["{}"]);
+    assert_eq!(braces("{a..}"), ["{a..}"]);
+    assert_eq!(braces("${a,b}"), ["${a,b}"]);
+    assert_eq!(braces("'{a,b}'"), ["{a,b}"]);
+    assert_eq!(braces("{a\\,b}"), ["{a,b}"]);
+    assert_eq!(braces("x{{a,b}"), ["x{a", "x{b"]);
+
+    // generated punctuation is not read as syntax by later expansions
+    let mut env = Env::default();
+    let expanded: Vec<String> = brace::expand(&word("{X..b}"))
+        .iter()
+        .flat_map(|word| expand::expand_word(word, &mut env).unwrap())
+        .collect();
+    assert_eq!(
+        expanded,
+        ["X", "Y", "Z", "[", "\\", "]", "^", "_", "`", "a", "b"]
+    );
+}
+
+#[derive(Default)]
+struct Env {
+    vars: HashMap<String, String>,
+    args: Vec<String>,
+    nounset: bool,
+}
+
+impl arith::Variables for Env {
+    fn get(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned()
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.set_var(name, value.to_string());
+    }
+
+    fn nounset(&self) -> bool {
+        self.nounset
+    }
+}
+
+impl Environment for Env {
+    fn set_var(&mut self, name: &str, value: String) {
+        self.vars.insert(name.to_owned(), value);
+    }
+
+    fn command_substitution(&mut self, command: &str) -> String {
+        command.strip_prefix("echo ").unwrap_or_default().to_owned()
+    }
+
+    fn glob_options(&self) -> GlobOptions {
+        GlobOptions::default()
+    }
+
+    fn fields(&self, name: &str) -> Option<Vec<String>> {
+        (name == "@").then(|| self.args.clone())
+    }
+}
+
+fn fields(env: &mut Env, input: &str) -> Vec<String> {
+    expand::expand_word(&word(input), env).unwrap()
+}
+
+#[test]
+fn test_expand_word() {
+    let mut env = Env::default();
+    env.set_var("x", "a | b > c".to_owned());
+    env.set_var("sp", "  one  two  ".to_owned());
+    env.set_var("empty", String::new());
+
+    // results of unquoted expansions are split, quoted ones are not
+    assert_eq!(fields(&mut env, "$x"), ["a", "|", "b", ">", "c"]);
+    assert_eq!(fields(&mut env, "\"$x\""), ["a | b > c"]);
+    assert_eq!(fields(&mut env, "x${sp}y"), ["x", "one", "two", "y"]);
+    assert_eq!(fields(&mut env, "'$x'"), ["$x"]);
+    assert_eq!(fields(&mut env, "\\$x"), ["$x"]);
+
+    // empty unquoted expansions vanish, quoted ones stay
+    assert!(fields(&mut env, "$empty").is_empty());
+    assert_eq!(fields(&mut env, "\"$empty\""), [""]);
+    assert_eq!(fields(&mut env, "''$empty"), [""]);
+
+    assert_eq!(fields(&mut env, "$(echo 1 2)"), ["1", "2"]);
+    assert_eq!(fields(&mut env, "\"$(echo 1 2)\""), ["1 2"]);
+    assert_eq!(fields(&mut env, "`echo a`b"), ["ab"]);
+    assert_eq!(fields(&mut env, "$((1 + 2))"), ["3"]);
+    assert_eq!(fields(&mut env, "${unset:-\"1 2\" 3}"), ["1 2", "3"]);
+    assert_eq!(fields(&mut env, "\"${unset:-1 2}\""), ["1 2"]);
+
+    env.set_var("IFS", ":".to_owned());
+    env.set_var("path", "a::b:".to_owned());
+    assert_eq!(fields(&mut env, "$path"), ["a", "", "b"]);
+    env.set_var("IFS", " :".to_owned());
+    env.set_var("path", " a : b ".to_owned());
+    assert_eq!(fields(&mut env, "$path"), ["a", "b"]);
+}
+
+#[test]
+fn test_expand_positional() {
+    let mut env = Env {
+        args: vec!["a b".to_owned(), String::new(), "c".to_owned()],
+        ..Env::default()
+    };
+
+    // each parameter is a field of its own, the outer ones joined to the
+    // text around them
+    assert_eq!(fields(&mut env, "\"$@\""), ["a b", "", "c"]);
+    assert_eq!(fields(&mut env, "\"${@}\""), ["a b", "", "c"]);
+    assert_eq!(fields(&mut env, "x\"<$@>\"y"), ["x<a b", "", "c>y"]);
+
+    env.args.clear();
+    assert!(fields(&mut env, "\"$@\"").is_empty());
+    assert_eq!(fields(&mut env, "\"x$@\""), ["x"]);
+}
+
+#[test]
+fn test_expand_nounset() {
+    let mut env = Env {
+        nounset: true,
+        ..Env::default()
+    };
+    let unbound = Err(ExpandError::Unbound("x".to_owned()));
+
+    assert_eq!(expand::expand_word(&word("$x"), &mut env), unbound);
+    assert_eq!(expand::expand_word(&word("\"${x%.*}\""), &mut env), unbound);
+    // forms that test whether it is set are fine, as are "$@" and "$*"
+    assert_eq!(fields(&mut env, "${x-a}${x:+b}"), ["a"]);
+    assert!(fields(&mut env, "$@$*").is_empty());
+    assert_eq!(
+        expand::expand_word(&word("$((x + 1))"), &mut env),
+        Err(ExpandError::Arithmetic(ArithError::Unbound("x".to_owned())))
+    );
+}
+
+#[test]
+fn test_expand_assignment() {
+    let mut env = Env::default();
+    env.set_var("HOME", "/home/me".to_owned());
+    env.set_var("x", "1  2".to_owned());
+
+    let assign = |env: &mut Env, input: &str| expand::expand_assignment(&word(input), env).unwrap();
+    assert_eq!(
+        assign(&mut env, "a=$x"),
+        ("a".to_owned(), "1  2".to_owned())
+    );
+    assert_eq!(
+        assign(&mut env, "p=~/bin:$x*"),
+        ("p".to_owned(), "/home/me/bin:1  2*".to_owned())
+    );
+
+    assert_eq!(
+        expand::expand_redirect(&word("$x"), &mut env),
+        Err(ExpandError::AmbiguousRedirect("$x".to_owned()))
+    );
+}
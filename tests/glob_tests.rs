@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use wsh::{
+    expand::{ExpandError, expand_glob},
+    glob::{self, GlobOptions},
+    scanner::{Word, WordPart},
+};
+
+/// Creates a fresh directory tree under the system temp dir.
+fn fixture(name: &str, files: &[&str]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("wsh-glob-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for file in files {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+    root
+}
+
+fn relative(root: &Path, paths: Vec<String>) -> Vec<String> {
+    let prefix = format!("{}/", root.display());
+    paths
+        .into_iter()
+        .map(|p| p.strip_prefix(&prefix).unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn test_matches() {
+    assert!(glob::matches("*.rs", "main.rs"));
+    assert!(!glob::matches("*.rs", "main.rs.bak"));
+    assert!(glob::matches("a?c", "abc"));
+    assert!(!glob::matches("a?c", "ac"));
+    assert!(glob::matches("[a-c]x", "bx"));
+    assert!(!glob::matches("[!a-c]x", "bx"));
+    assert!(glob::matches("[^a-c]x", "dx"));
+    assert!(glob::matches("[]]", "]"));
+    assert!(glob::matches("[[:digit:]]*", "1abc"));
+    assert!(glob::matches("\\*", "*"));
+    assert!(!glob::matches("\\*", "a"));
+    assert!(glob::matches("[x", "[x"));
+    assert!(glob::matches("*a*b*", "xxaybzz"));
+    assert!(!glob::has_magic("plain\\*"));
+    assert!(glob::has_magic("a[bc]"));
+}
+
+#[test]
+fn test_expand() {
+    let root = fixture(
+        "expand",
+        &[
+            "b.rs",
+            "a.rs",
+            "c.toml",
+            ".hidden.rs",
+            "src/x.rs",
+            "src/deep/y.rs",
+        ],
+    );
+    let base = glob::escape(&root.display().to_string());
+    let opts = GlobOptions::default();
+
+    let found = glob::expand(&format!("{base}/*.rs"), &opts);
+    assert_eq!(relative(&root, found), vec!["a.rs", "b.rs"]);
+
+    let dotglob = GlobOptions {
+        dotglob: true,
+        ..opts
+    };
+    let found = glob::expand(&format!("{base}/*.rs"), &dotglob);
+    assert_eq!(relative(&root, found), vec![".hidden.rs", "a.rs", "b.rs"]);
+
+    let found = glob::expand(&format!("{base}/**/*.rs"), &opts);
+    assert_eq!(
+        relative(&root, found),
+        vec!["a.rs", "b.rs", "src/deep/y.rs", "src/x.rs"]
+    );
+
+    let found = glob::expand(&format!("{base}/*/"), &opts);
+    assert_eq!(relative(&root, found), vec!["src/"]);
+
+    let found = glob::expand(&format!("{base}/[ab].*"), &opts);
+    assert_eq!(relative(&root, found), vec!["a.rs", "b.rs"]);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_expand_word() {
+    let root = fixture("word", &["a.txt", "*.txt"]);
+    let base = root.display().to_string();
+    let opts = GlobOptions::default();
+
+    // unquoted wildcards expand
+    let word = Word::new(vec![WordPart::Unquoted(format!("{base}/*.txt"))]);
+    assert_eq!(expand_glob(word, &opts).unwrap().len(), 2);
+
+    // quoted ones stay literal
+    let word = Word::new(vec![
+        WordPart::Unquoted(format!("{base}/")),
+        WordPart::DoubleQuoted("*".to_owned()),
+        WordPart::Unquoted(".txt".to_owned()),
+    ]);
+    let words = expand_glob(word.clone(), &opts).unwrap();
+    assert_eq!(words.len(), 1);
+    assert_eq!(words[0].text(), format!("{base}/*.txt"));
+
+    let missing = Word::new(vec![WordPart::Unquoted(format!("{base}/*.none"))]);
+    assert_eq!(
+        expand_glob(missing.clone(), &opts).unwrap(),
+        vec![missing.clone()]
+    );
+
+    let nullglob = GlobOptions {
+        nullglob: true,
+        ..opts
+    };
+    assert!(expand_glob(missing.clone(), &nullglob).unwrap().is_empty());
+
+    let failglob = GlobOptions {
+        failglob: true,
+        ..opts
+    };
+    assert_eq!(
+        expand_glob(missing, &failglob),
+        Err(ExpandError::NoMatch(format!("{base}/*.none")))
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
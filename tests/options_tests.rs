@@ -0,0 +1,30 @@
+use wsh::options::Options;
+
+#[test]
+fn test_options() {
+    let mut options = Options::default();
+    assert_eq!(options.flags(), "");
+
+    for letter in ['e', 'u', 'x'] {
+        let name = Options::name(letter).unwrap();
+        *options.get_mut(name).unwrap() = true;
+    }
+    *options.get_mut("pipefail").unwrap() = true;
+    assert!(options.errexit && options.nounset && options.xtrace && options.pipefail);
+    assert_eq!(options.flags(), "eux");
+
+    assert_eq!(Options::name('C'), Some("noclobber"));
+    assert_eq!(Options::name('q'), None);
+    assert_eq!(options.get("noclobber"), Some(false));
+    assert!(options.get_mut("nope").is_none());
+    assert_eq!(
+        options.list(),
+        [
+            ("errexit", true),
+            ("noclobber", false),
+            ("nounset", true),
+            ("pipefail", true),
+            ("xtrace", true),
+        ]
+    );
+}
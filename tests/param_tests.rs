@@ -0,0 +1,115 @@
+use wsh::param::{self, ParamExpansion, ParamOp, ReplaceMode};
+
+#[test]
+fn test_parse() {
+    let parse = |body| param::parse(body).unwrap();
+
+    assert_eq!(
+        parse("HOME"),
+        ParamExpansion {
+            name: "HOME".to_owned(),
+            op: ParamOp::Plain
+        }
+    );
+    assert_eq!(parse("#v").op, ParamOp::Length);
+    assert_eq!(parse("#").name, "#");
+    assert_eq!(parse("PIPESTATUS[1]").name, "PIPESTATUS[1]");
+    assert_eq!(parse("#PIPESTATUS[@]").op, ParamOp::Length);
+    assert_eq!(
+        parse("v:-a b").op,
+        ParamOp::Default {
+            word: "a b".to_owned(),
+            colon: true
+        }
+    );
+    assert_eq!(
+        parse("v=x").op,
+        ParamOp::Assign {
+            word: "x".to_owned(),
+            colon: false
+        }
+    );
+    assert_eq!(
+        parse("v##*/").op,
+        ParamOp::TrimPrefix {
+            pattern: "*/".to_owned(),
+            longest: true
+        }
+    );
+    assert_eq!(
+        parse("v:1:3").op,
+        ParamOp::Substring {
+            offset: "1".to_owned(),
+            length: Some("3".to_owned())
+        }
+    );
+    assert_eq!(
+        parse("v//a/b").op,
+        ParamOp::Replace {
+            pattern: "a".to_owned(),
+            replacement: "b".to_owned(),
+            mode: ReplaceMode::All
+        }
+    );
+    assert_eq!(
+        parse("v^^").op,
+        ParamOp::Case {
+            upper: true,
+            all: true,
+            pattern: String::new()
+        }
+    );
+    assert_eq!(parse("10").name, "10");
+
+    assert_eq!(param::parse(""), None);
+    assert_eq!(param::parse("a b"), None);
+    assert_eq!(param::parse("{x}"), None);
+}
+
+#[test]
+fn test_operations() {
+    let path = "/home/user/archive.tar.gz";
+    assert_eq!(
+        param::trim_prefix(path, "*/", false),
+        "home/user/archive.tar.gz"
+    );
+    assert_eq!(param::trim_prefix(path, "*/", true), "archive.tar.gz");
+    assert_eq!(
+        param::trim_suffix(path, ".*", false),
+        "/home/user/archive.tar"
+    );
+    assert_eq!(param::trim_suffix(path, ".*", true), "/home/user/archive");
+    assert_eq!(param::trim_suffix(path, "x*", true), path);
+
+    assert_eq!(
+        param::replace("banana", "a", "o", ReplaceMode::First),
+        "bonana"
+    );
+    assert_eq!(
+        param::replace("banana", "a", "o", ReplaceMode::All),
+        "bonono"
+    );
+    assert_eq!(
+        param::replace("banana", "b*n", "X", ReplaceMode::First),
+        "Xa"
+    );
+    assert_eq!(
+        param::replace("banana", "ba", "X", ReplaceMode::Prefix),
+        "Xnana"
+    );
+    assert_eq!(
+        param::replace("banana", "na", "X", ReplaceMode::Suffix),
+        "banaX"
+    );
+    assert_eq!(param::replace("banana", "an", "", ReplaceMode::All), "ba");
+
+    assert_eq!(param::change_case("hello", true, false, ""), "Hello");
+    assert_eq!(param::change_case("hello", true, true, ""), "HELLO");
+    assert_eq!(param::change_case("hello", true, true, "[lo]"), "heLLO");
+    assert_eq!(param::change_case("HeLLo", false, true, ""), "hello");
+
+    assert_eq!(param::substring("abcdef", 1, Some(3)), "bcd");
+    assert_eq!(param::substring("abcdef", -2, None), "ef");
+    assert_eq!(param::substring("abcdef", 1, Some(-2)), "bcd");
+    assert_eq!(param::substring("abcdef", 10, None), "");
+}
@@ -0,0 +1,137 @@
+use wsh::{
+    parser::{self, Command, Compound, Connector, ParseError, ParseErrorKind, Program},
+    scanner::{RedirectOp, Scanner, Span, Word},
+};
+
+fn parse(input: &str) -> Result<Program, ParseError> {
+    let tokens = Scanner::new(input).scan_tokens().unwrap();
+    parser::parse(&tokens, input)
+}
+
+#[test]
+fn test_parser() {
+    let program = parse("a=1 b=2 echo $a > out | wc -l && ! true; sleep 1 &").unwrap();
+    let items = &program.body.items;
+    assert_eq!(items.len(), 2);
+
+    let first = &items[0];
+    assert!(!first.background);
+    assert_eq!(first.text, "a=1 b=2 echo $a > out | wc -l && ! true");
+    assert_eq!(first.first.commands.len(), 2);
+    assert_eq!(first.first.text, "a=1 b=2 echo $a > out | wc -l");
+
+    let Command::Simple(echo) = &first.first.commands[0] else {
+        panic!("expected a simple command");
+    };
+    assert_eq!(echo.assignments, vec![Word::from("a=1"), Word::from("b=2")]);
+    assert_eq!(echo.words.len(), 2);
+    assert_eq!(echo.redirects.len(), 1);
+    assert_eq!(echo.redirects[0].fd, 1);
+    assert_eq!(echo.redirects[0].op, RedirectOp::Output);
+
+    let [(connector, pipeline)] = &first.rest[..] else {
+        panic!("expected one more pipeline");
+    };
+    assert_eq!(*connector, Connector::And);
+    assert!(pipeline.negated);
+
+    assert!(items[1].background);
+    assert_eq!(items[1].text, "sleep 1");
+}
+
+#[test]
+fn test_parser_compound() {
+    let program = parse("f() { for x in a b; do echo $x; done; } 2>/dev/null").unwrap();
+    let Command::FunctionDef { name, function } = &program.body.items[0].first.commands[0] else {
+        panic!("expected a function definition");
+    };
+    assert_eq!(name, "f");
+    assert_eq!(
+        function.text,
+        "f() { for x in a b; do echo $x; done; } 2>/dev/null"
+    );
+
+    let Command::Compound {
+        compound: Compound::Group(body),
+        redirects,
+    } = &function.body
+    else {
+        panic!("expected a group");
+    };
+    assert_eq!(redirects.len(), 1);
+    assert!(matches!(
+        body.items[0].first.commands[0],
+        Command::Compound {
+            compound: Compound::For { .. },
+            ..
+        }
+    ));
+
+    let program = parse("x=1\n((x > 0))\n").unwrap();
+    let Command::Simple(assign) = &program.body.items[0].first.commands[0] else {
+        panic!("expected a simple command");
+    };
+    assert!(assign.words.is_empty());
+    assert!(matches!(
+        &program.body.items[1].first.commands[0],
+        Command::Arith(expr) if expr == "x > 0"
+    ));
+}
+
+#[test]
+fn test_parser_errors() {
+    let source = "echo a && ; echo b";
+    let err = parse(source).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::Unexpected(";".to_owned()));
+    assert_eq!(err.span, Span::new(10, 11));
+    assert_eq!(
+        err.render(source),
+        "echo a && ; echo b\n          ^ syntax error near unexpected token `;`"
+    );
+
+    let err = parse("if true; then echo").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::UnexpectedEof);
+    assert_eq!(err.span, Span::new(18, 18));
+
+    let err = parse("echo one\nwhile do").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::Unexpected("do".to_owned()));
+    assert_eq!(
+        err.render("echo one\nwhile do"),
+        "while do\n      ^^ syntax error near unexpected token `do`"
+    );
+
+    let err = parse("case x in a) b;; c d) e;; esac").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::Unexpected("d".to_owned()));
+}
+
+#[test]
+fn test_parser_incomplete() {
+    for input in [
+        "echo 'a",
+        "echo \"a",
+        "echo $(ls",
+        "ls |",
+        "true &&",
+        "false ||\n",
+        "if true; then echo",
+        "while true\ndo",
+        "f() {",
+        "case x in",
+        "cat <<EOF\nbody",
+        "(echo",
+    ] {
+        assert!(parser::is_incomplete(input), "{input:?} is incomplete");
+    }
+
+    for input in [
+        "",
+        "echo a # 'open",
+        "if true; then echo; fi",
+        "cat <<EOF\nbody\nEOF",
+        "echo a; ;",
+        "( )",
+        "if then",
+    ] {
+        assert!(!parser::is_incomplete(input), "{input:?} is complete");
+    }
+}
@@ -1,7 +1,12 @@
-use wsh::{scanner::Scanner, scanner::Token};
+use wsh::scanner::{RedirectOp, ScanErrorKind, Scanner, Span, TokenKind, Word, WordPart};
 
-fn tokenize(input: &str) -> Vec<Token> {
-    Scanner::new(input).scan_tokens().unwrap()
+fn tokenize(input: &str) -> Vec<TokenKind> {
+    Scanner::new(input)
+        .scan_tokens()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect()
 }
 
 #[test]
@@ -10,11 +15,13 @@ fn test_scanner() {
     assert_eq!(
         tokens,
         vec![
-            Token::Literal("echo".to_owned()),
-            Token::Literal("hello world".to_owned()),
-            Token::Pipe,
-            Token::Literal("wc".to_owned()),
-            Token::Eof
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal(Word::new(vec![WordPart::DoubleQuoted(
+                "hello world".to_owned()
+            )])),
+            TokenKind::Pipe,
+            TokenKind::Literal("wc".into()),
+            TokenKind::Eof
         ]
     );
 
@@ -22,9 +29,9 @@ fn test_scanner() {
     assert_eq!(
         tokens,
         vec![
-            Token::Literal("ls".to_owned()),
-            Token::Literal("-la".to_owned()),
-            Token::Eof
+            TokenKind::Literal("ls".into()),
+            TokenKind::Literal("-la".into()),
+            TokenKind::Eof
         ]
     );
 
@@ -32,13 +39,15 @@ fn test_scanner() {
     assert_eq!(
         tokens,
         vec![
-            Token::Literal("echo".to_owned()),
-            Token::Literal(" this is | a $ test  ".to_owned()),
-            Token::Pipe,
-            Token::Literal("wc".to_owned()),
-            Token::Pipe,
-            Token::Literal("wc".to_owned()),
-            Token::Eof
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal(Word::new(vec![WordPart::DoubleQuoted(
+                " this is | a $ test  ".to_owned()
+            )])),
+            TokenKind::Pipe,
+            TokenKind::Literal("wc".into()),
+            TokenKind::Pipe,
+            TokenKind::Literal("wc".into()),
+            TokenKind::Eof
         ]
     );
 
@@ -46,25 +55,383 @@ fn test_scanner() {
     assert_eq!(
         tokens,
         vec![
-            Token::Literal("echo".to_owned()),
-            Token::Literal("hello".to_owned()),
-            Token::Greater,
-            Token::Literal("out.txt".to_owned()),
-            Token::Eof,
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal("hello".into()),
+            TokenKind::Redirect {
+                fd: None,
+                op: RedirectOp::Output
+            },
+            TokenKind::Literal("out.txt".into()),
+            TokenKind::Eof,
         ]
     )
 }
 
 #[test]
-#[should_panic]
-fn test_scanner_panic() {
+fn test_scanner_words() {
     let tokens = tokenize("ls -la *");
     assert_eq!(
         tokens,
         vec![
-            Token::Literal("ls".to_owned()),
-            Token::Literal("-la".to_owned()),
-            Token::Eof
+            TokenKind::Literal("ls".into()),
+            TokenKind::Literal("-la".into()),
+            TokenKind::Literal("*".into()),
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = tokenize("git commit -m fix+test user@host 50% a,b ~/x --opt=[1] héllo");
+    let words: Vec<TokenKind> = [
+        "git",
+        "commit",
+        "-m",
+        "fix+test",
+        "user@host",
+        "50%",
+        "a,b",
+        "~/x",
+        "--opt=[1]",
+        "héllo",
+    ]
+    .into_iter()
+    .map(|w| TokenKind::Literal(w.into()))
+    .chain([TokenKind::Eof])
+    .collect();
+    assert_eq!(tokens, words);
+
+    let tokens = tokenize("cat<in|wc>out");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("cat".into()),
+            TokenKind::Redirect {
+                fd: None,
+                op: RedirectOp::Input
+            },
+            TokenKind::Literal("in".into()),
+            TokenKind::Pipe,
+            TokenKind::Literal("wc".into()),
+            TokenKind::Redirect {
+                fd: None,
+                op: RedirectOp::Output
+            },
+            TokenKind::Literal("out".into()),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_quotes() {
+    let tokens = tokenize("echo 'a $b | c' \"x\\\"y\"");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal(Word::new(vec![WordPart::SingleQuoted(
+                "a $b | c".to_owned()
+            )])),
+            TokenKind::Literal(Word::new(vec![
+                WordPart::DoubleQuoted("x".to_owned()),
+                WordPart::Escaped('"'),
+                WordPart::DoubleQuoted("y".to_owned()),
+            ])),
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = tokenize("foo\"bar\"'baz' \\$x \"\\n\" ''");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal(Word::new(vec![
+                WordPart::Unquoted("foo".to_owned()),
+                WordPart::DoubleQuoted("bar".to_owned()),
+                WordPart::SingleQuoted("baz".to_owned()),
+            ])),
+            TokenKind::Literal(Word::new(vec![
+                WordPart::Escaped('$'),
+                WordPart::Unquoted("x".to_owned())
+            ])),
+            TokenKind::Literal(Word::new(vec![WordPart::DoubleQuoted("\\n".to_owned())])),
+            TokenKind::Literal(Word::new(vec![WordPart::SingleQuoted(String::new())])),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_word_text() {
+    let tokens = tokenize("a\"b c\"'d'\\|e");
+    match &tokens[0] {
+        TokenKind::Literal(word) => {
+            assert_eq!(word.text(), "ab cd|e");
+            assert!(word.is_quoted());
+        }
+        t => panic!("expected literal, got {t:?}"),
+    }
+}
+
+#[test]
+fn test_scanner_spans() {
+    let tokens = Scanner::new("echo 'hé' > out").scan_tokens().unwrap();
+    let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+    assert_eq!(
+        spans,
+        vec![
+            Span::new(0, 4),
+            Span::new(5, 10),
+            Span::new(11, 12),
+            Span::new(13, 16),
+            Span::new(16, 16),
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_errors() {
+    let source = "echo \"abc";
+    let err = Scanner::new(source).scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::UnterminatedQuote('"'));
+    assert_eq!(err.span, Span::new(5, 9));
+    assert_eq!(
+        err.render(source),
+        "echo \"abc\n     ^^^^ unterminated \" quote"
+    );
+
+    let err = Scanner::new("ls 'x").scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::UnterminatedQuote('\''));
+
+    let err = Scanner::new("echo hi >  | wc").scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::BadRedirection(">"));
+    assert_eq!(
+        err.render("echo hi >  | wc"),
+        "echo hi >  | wc\n        ^ expected a file name after >"
+    );
+
+    let err = Scanner::new("cat <").scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::BadRedirection("<"));
+
+    let err = Scanner::new("echo $(ls").scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::UnterminatedSubstitution("$("));
+    assert_eq!(err.span, Span::new(5, 9));
+}
+
+#[test]
+fn test_scanner_substitutions() {
+    // substitutions stay whole, metacharacters and quotes included
+    let tokens = tokenize("echo $(ls | wc -l)x ${v:-a b} `cat <f`");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal("$(ls | wc -l)x".into()),
+            TokenKind::Literal("${v:-a b}".into()),
+            TokenKind::Literal("`cat <f`".into()),
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = tokenize("\"$(echo \")\")\" $(echo ')')");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal(Word::new(vec![WordPart::DoubleQuoted(
+                "$(echo \")\")".to_owned()
+            )])),
+            TokenKind::Literal("$(echo ')')".into()),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_heredocs() {
+    let tokens = tokenize("cat <<EOF | wc <<-'END'\nhi $x \\$y\nEOF\n\tliteral $x\n\tEND\n");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("cat".into()),
+            TokenKind::HereDoc {
+                fd: None,
+                body: Word::new(vec![
+                    WordPart::DoubleQuoted("hi $x ".to_owned()),
+                    WordPart::Escaped('$'),
+                    WordPart::DoubleQuoted("y\n".to_owned()),
+                ]),
+            },
+            TokenKind::Pipe,
+            TokenKind::Literal("wc".into()),
+            TokenKind::HereDoc {
+                fd: None,
+                body: Word::new(vec![WordPart::SingleQuoted("literal $x\n".to_owned())]),
+            },
+            TokenKind::Newline,
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = tokenize("cat <<< \"a b\"");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("cat".into()),
+            TokenKind::Redirect {
+                fd: None,
+                op: RedirectOp::HereString
+            },
+            TokenKind::Literal(Word::new(vec![WordPart::DoubleQuoted("a b".to_owned())])),
+            TokenKind::Eof
+        ]
+    );
+
+    let err = Scanner::new("cat <<EOF\nline\n").scan_tokens().unwrap_err();
+    assert_eq!(
+        err.kind,
+        ScanErrorKind::UnterminatedHereDoc("EOF".to_owned())
+    );
+    assert_eq!(err.span.start, 4);
+}
+
+#[test]
+fn test_scanner_redirections() {
+    let redirect = |fd, op| TokenKind::Redirect { fd, op };
+    let tokens = tokenize("cmd 2>>log 2>&1 >|out 3<>rw 4>&- &>all &>>both 12<in x2>y");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("cmd".into()),
+            redirect(Some(2), RedirectOp::Append),
+            TokenKind::Literal("log".into()),
+            redirect(Some(2), RedirectOp::DupOutput),
+            TokenKind::Literal("1".into()),
+            redirect(None, RedirectOp::Clobber),
+            TokenKind::Literal("out".into()),
+            redirect(Some(3), RedirectOp::ReadWrite),
+            TokenKind::Literal("rw".into()),
+            redirect(Some(4), RedirectOp::DupOutput),
+            TokenKind::Literal("-".into()),
+            redirect(None, RedirectOp::OutputAll),
+            TokenKind::Literal("all".into()),
+            redirect(None, RedirectOp::AppendAll),
+            TokenKind::Literal("both".into()),
+            redirect(Some(12), RedirectOp::Input),
+            TokenKind::Literal("in".into()),
+            TokenKind::Literal("x2".into()),
+            redirect(None, RedirectOp::Output),
+            TokenKind::Literal("y".into()),
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = Scanner::new("ls 2>&1").scan_tokens().unwrap();
+    assert_eq!(tokens[1].span, Span::new(3, 6));
+
+    let err = Scanner::new("ls 2>").scan_tokens().unwrap_err();
+    assert_eq!(err.kind, ScanErrorKind::BadRedirection(">"));
+    assert_eq!(err.span, Span::new(3, 5));
+}
+
+#[test]
+fn test_scanner_lists() {
+    let tokens = tokenize("a && b || c; d\n!e");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("a".into()),
+            TokenKind::AndIf,
+            TokenKind::Literal("b".into()),
+            TokenKind::OrIf,
+            TokenKind::Literal("c".into()),
+            TokenKind::Semi,
+            TokenKind::Literal("d".into()),
+            TokenKind::Newline,
+            TokenKind::Literal("!e".into()),
+            TokenKind::Eof
+        ]
+    );
+
+    let tokens = tokenize("a) b;; c;d");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("a".into()),
+            TokenKind::RParen,
+            TokenKind::Literal("b".into()),
+            TokenKind::DSemi,
+            TokenKind::Literal("c".into()),
+            TokenKind::Semi,
+            TokenKind::Literal("d".into()),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_background() {
+    let tokens = tokenize("sleep 1 & ls&>out &");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::Literal("sleep".into()),
+            TokenKind::Literal("1".into()),
+            TokenKind::Amp,
+            TokenKind::Literal("ls".into()),
+            TokenKind::Redirect {
+                fd: None,
+                op: RedirectOp::OutputAll
+            },
+            TokenKind::Literal("out".into()),
+            TokenKind::Amp,
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_subshells() {
+    let tokens = tokenize("(cd a && ls)|{ wc; }");
+    assert_eq!(
+        tokens,
+        vec![
+            TokenKind::LParen,
+            TokenKind::Literal("cd".into()),
+            TokenKind::Literal("a".into()),
+            TokenKind::AndIf,
+            TokenKind::Literal("ls".into()),
+            TokenKind::RParen,
+            TokenKind::Pipe,
+            TokenKind::Literal("{".into()),
+            TokenKind::Literal("wc".into()),
+            TokenKind::Semi,
+            TokenKind::Literal("}".into()),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn test_scanner_comments() {
+    assert_eq!(
+        tokenize("echo a#b # comment | wc\n# whole line\nls"),
+        vec![
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal("a#b".into()),
+            TokenKind::Newline,
+            TokenKind::Newline,
+            TokenKind::Literal("ls".into()),
+            TokenKind::Eof
+        ]
+    );
+    assert_eq!(
+        tokenize("echo '#' \\# x;#y"),
+        vec![
+            TokenKind::Literal("echo".into()),
+            TokenKind::Literal(Word::new(vec![WordPart::SingleQuoted("#".to_owned())])),
+            TokenKind::Literal(Word::new(vec![WordPart::Escaped('#')])),
+            TokenKind::Literal("x".into()),
+            TokenKind::Semi,
+            TokenKind::Eof
         ]
     );
 }
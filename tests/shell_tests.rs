@@ -0,0 +1,698 @@
+use std::{
+    io::{self, Read, Write},
+    process::{Child, Command, Stdio},
+};
+
+/// Starts wsh with `args`, its output and errors going to the same pipe so
+/// that they stay in order.
+fn spawn(args: &[&str], stdin: Stdio) -> (Child, io::PipeReader) {
+    let (reader, writer) = io::pipe().unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_wsh"))
+        .args(args)
+        .stdin(stdin)
+        .stdout(writer.try_clone().unwrap())
+        .stderr(writer)
+        .spawn()
+        .expect("failed to start wsh");
+    (child, reader)
+}
+
+/// Reads everything printed until the shell exits, and its exit code.
+fn finish(mut child: Child, mut reader: io::PipeReader) -> (String, i32) {
+    let mut output = String::new();
+    reader.read_to_string(&mut output).unwrap();
+    (output, child.wait().unwrap().code().unwrap())
+}
+
+/// Feeds `input` to a fresh shell and returns everything it printed,
+/// errors included.
+fn run(input: &str) -> String {
+    let (mut child, reader) = spawn(&[], Stdio::piped());
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    finish(child, reader).0
+}
+
+#[test]
+fn test_command_substitution() {
+    assert_eq!(run("echo $(echo hello   world)\n"), "hello world\n");
+    assert_eq!(run("echo \"$(printf 'a  b')\"\n"), "a  b\n");
+    assert_eq!(run("echo `echo x\\`echo y\\``\n"), "xy\n");
+    assert_eq!(
+        run("echo $(echo $(echo nested) | tr a-z A-Z)\n"),
+        "NESTED\n"
+    );
+    assert_eq!(run("echo $(printf 'a\\n\\n\\n')b\n"), "ab\n");
+    assert_eq!(run("echo '$(echo no)'\n"), "$(echo no)\n");
+    assert_eq!(run("echo $(echo \"it's | here\")\n"), "it's | here\n");
+    // a `case` pattern's `)` does not end the substitution
+    assert_eq!(
+        run("echo $(case a in a) echo yes;; esac) \"$(echo ')')\"\n"),
+        "yes )\n"
+    );
+    // assignments alone take the status of the last substitution
+    assert_eq!(
+        run("x=$(exit 3)\necho $?\ny=$(true) z=$(exit 4)\necho $? $(exit 5)\necho $?\n"),
+        "3\n4\n0\n"
+    );
+    assert_eq!(run_args(&["-c", "set -e; x=$(false); echo no"]).1, 1);
+    // errors are not part of the output
+    assert_eq!(
+        run("x=$(nosuchcmd)\necho \"[$x]\"\n"),
+        "command not found: nosuchcmd\n[]\n"
+    );
+    assert_eq!(
+        run(
+            "nosuchcmd 2>/dev/null\n{ cd /nonexistent; (echo ${x:?unset}); } 2>/dev/null\necho end\n"
+        ),
+        "end\n"
+    );
+}
+
+#[test]
+fn test_arithmetic() {
+    assert_eq!(run("echo $((1 + 2 * 3)) $(( (1 + 2) * 3 ))\n"), "7 9\n");
+    assert_eq!(
+        run("i=5\n((i += 1))\nlet i*=2 j=i+1\necho $i $j\n"),
+        "12 13\n"
+    );
+    assert_eq!(run("echo $(( $(echo 4) + 1 ))\n"), "5\n");
+}
+
+#[test]
+fn test_parameter_expansion() {
+    let input = "f=/tmp/archive.tar.gz\necho ${f##*/} ${f%%.*} ${#f} ${f:5:7} ${f//a/A}\n";
+    assert_eq!(
+        run(input),
+        "archive.tar.gz /tmp/archive 19 archive /tmp/Archive.tAr.gz\n"
+    );
+    assert_eq!(
+        run("echo ${unset:-default} ${x:=set} $x ${x:+alt} ${x^^}\n"),
+        "default set set alt SET\n"
+    );
+    assert_eq!(
+        run_args(&["-c", "echo ${x:?is required}; echo after"]),
+        ("x: is required\n".to_owned(), 127)
+    );
+    // the message is an error, and ends the subshell
+    assert_eq!(run("(echo ${x?}) 2>/dev/null\necho $?\n"), "127\n");
+}
+
+#[test]
+fn test_word_expansion() {
+    // operators inside variables are never parsed as syntax
+    assert_eq!(
+        run("x='a | b > c'\nprintf '[%s]' $x \"$x\"\n"),
+        "[a][|][b][>][c][a | b > c]"
+    );
+    assert_eq!(run("e=\nprintf '[%s]' $e \"$e\" x\n"), "[][x]");
+    assert_eq!(run("v='*'\necho \"$v\"\n"), "*\n");
+}
+
+#[test]
+fn test_heredocs() {
+    assert_eq!(
+        run("x=world\ncat <<EOF\nhello $x\n\\$x \"$(echo sub)\"\nEOF\n"),
+        "hello world\n$x \"sub\"\n"
+    );
+    assert_eq!(run("cat <<'EOF'\n$x `no`\nEOF\n"), "$x `no`\n");
+    assert_eq!(run("cat <<-EOF\n\t\tindented\n\tEOF\n"), "indented\n");
+    assert_eq!(run("x=1\ncat <<< \"x is $x\"\n"), "x is 1\n");
+}
+
+#[test]
+fn test_redirections() {
+    let dir = std::env::temp_dir().join(format!("wsh-redirect-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("out").display().to_string();
+
+    // applied left to right: stderr goes to the pipe, stdout to the file
+    let input = format!("sh -c 'echo err >&2; echo out' 2>&1 >{file} | tr a-z A-Z\ncat {file}\n");
+    assert_eq!(run(&input), "ERR\nout\n");
+
+    let input = format!("echo a > {file}\necho b >> {file}\npwd >> {file}\ncat {file}\n");
+    assert_eq!(
+        run(&input),
+        format!("a\nb\n{}\n", std::env::current_dir().unwrap().display())
+    );
+
+    let input = format!("set -C\necho new > {file}\necho forced >| {file}\ncat < {file}\n");
+    assert_eq!(
+        run(&input),
+        format!("{file}: cannot overwrite existing file\nforced\n")
+    );
+
+    let input = format!("ls {file} {file}-missing &> {file}\nwc -l < {file}\n");
+    assert!(run(&input).ends_with("2\n"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_lists() {
+    assert_eq!(run("echo a; echo b;\n"), "a\nb\n");
+    assert_eq!(run("cd /; pwd\n"), "/\n");
+    assert_eq!(
+        run("true && echo yes || echo no\n! true && echo yes || echo no\n"),
+        "yes\nno\n"
+    );
+    assert_eq!(
+        run("nosuchcmd-wsh || echo fallback\n"),
+        "command not found: nosuchcmd-wsh\nfallback\n"
+    );
+    assert_eq!(run("x=1; echo \"a;b\" $x\n"), "a;b 1\n");
+    assert_eq!(
+        run("echo a && ; echo b\n"),
+        "echo a && ; echo b\n          ^ syntax error near unexpected token `;`\n"
+    );
+}
+
+#[test]
+fn test_background_jobs() {
+    assert_eq!(run("echo a & wait; echo b\n"), "a\nb\n");
+    assert_eq!(
+        run("sleep 0.2 & jobs\nwait\njobs\n"),
+        "[1]+  Running                 sleep 0.2 &\n"
+    );
+    assert_eq!(
+        run("sleep 0.2 & sleep 0.3 & jobs %-\njobs %?0.\n"),
+        "[1]-  Running                 sleep 0.2 &\njobs: %?0.: ambiguous job spec\n"
+    );
+    assert_eq!(run("let 0 & wait %1 || echo failed\n"), "failed\n");
+    assert_eq!(run("true & test -n \"$!\" && echo pid\n"), "pid\n");
+    assert_eq!(
+        run("sleep 0.2 & disown\njobs\nwait %1\n"),
+        "wait: %1: no such job\n"
+    );
+    assert_eq!(run("fg\n"), "fg: no job control\n");
+}
+
+#[test]
+fn test_subshells_and_groups() {
+    assert_eq!(run("(cd / && pwd); { cd /; }; pwd\n"), "/\n/\n");
+    assert_eq!(run("(x=1); { y=2; }; echo $x-$y\n"), "-2\n");
+    assert_eq!(run("(echo a; echo b) | tr a-z A-Z\n"), "A\nB\n");
+    assert_eq!(run("{ echo x; echo y; } | wc -l\n"), "2\n");
+    assert_eq!(run("echo a | { cat; echo got; }\n"), "a\ngot\n");
+    assert_eq!(run("(exit); echo after\n"), "after\n");
+    assert_eq!(
+        run("( )\n{ echo a }\n"),
+        concat!(
+            "( )\n  ^ syntax error near unexpected token `)`\n",
+            "{ echo a }\n          ^ syntax error: unexpected end of input\n",
+        )
+    );
+
+    let file = std::env::temp_dir().join(format!("wsh-subshell-{}", std::process::id()));
+    let file = file.display();
+    let input =
+        format!("(echo out; echo err >&2) > {file} 2>&1\n{{ echo more; }} >> {file}\ncat {file}\n");
+    assert_eq!(run(&input), "out\nerr\nmore\n");
+    std::fs::remove_file(file.to_string()).unwrap();
+}
+
+#[test]
+fn test_control_flow() {
+    assert_eq!(
+        run("if ((0)); then echo no; elif ((1)); then echo elif; else echo else; fi\n"),
+        "elif\n"
+    );
+    assert_eq!(
+        run("if ! true; then echo no; else echo else; fi\n"),
+        "else\n"
+    );
+    assert_eq!(
+        run("i=0; while ((i < 3)); do echo $i; ((i++)); done\n"),
+        "0\n1\n2\n"
+    );
+    assert_eq!(
+        run("i=0; until ((i == 2)); do ((i++)); done; echo $i\n"),
+        "2\n"
+    );
+    assert_eq!(
+        run("for f in a \"b c\" {1..2}; do echo \"<$f>\"; done\n"),
+        "<a>\n<b c>\n<1>\n<2>\n"
+    );
+    assert_eq!(
+        run("for x in a b; do echo $x; done | tr a-z A-Z\n"),
+        "A\nB\n"
+    );
+    assert_eq!(
+        run("if true; then echo out; fi > /dev/null; echo after\n"),
+        "after\n"
+    );
+}
+
+#[test]
+fn test_loop_control() {
+    assert_eq!(
+        run(
+            "for x in 1 2; do for y in a b c; do case $y in b) continue 2;; esac; echo $x$y; done; done\n"
+        ),
+        "1a\n2a\n"
+    );
+    assert_eq!(
+        run("for x in 1 2; do for y in a b; do break 2; done; echo no; done; echo broke\n"),
+        "broke\n"
+    );
+    assert_eq!(run("while true; do echo once; break; done\n"), "once\n");
+    assert_eq!(
+        run("break\n"),
+        "break: only meaningful in a `for', `while', or `until' loop\n"
+    );
+    assert_eq!(
+        run("for x in a; do continue 0; done\n"),
+        "continue: 0: loop count out of range\n"
+    );
+}
+
+#[test]
+fn test_case() {
+    let input = "for f in a.rs b.txt c; do case $f in *.txt) echo text;; *.rs|*.c) echo source;; *) echo other;; esac; done\n";
+    assert_eq!(run(input), "source\ntext\nother\n");
+    assert_eq!(run("case 'a*' in \"a*\") echo quoted;; esac\n"), "quoted\n");
+    assert_eq!(
+        run("case ab in a\\*) echo no;; (a?) echo yes; esac\n"),
+        "yes\n"
+    );
+    assert_eq!(run("case z in a) echo a;; esac; echo done\n"), "done\n");
+    assert_eq!(
+        run("if then fi\nfor 1 in a; do :; done\n"),
+        concat!(
+            "if then fi\n   ^^^^ syntax error near unexpected token `then`\n",
+            "for 1 in a; do :; done\n    ^ syntax error near unexpected token `1`\n",
+        )
+    );
+}
+
+#[test]
+fn test_functions() {
+    assert_eq!(
+        run("greet() { echo \"hello $1 and $2\"; }\ngreet world you\n"),
+        "hello world and you\n"
+    );
+    assert_eq!(
+        run("function up { echo \"$1\" | tr a-z A-Z; }; up abc; up x | tr X Y\n"),
+        "ABC\nY\n"
+    );
+    assert_eq!(
+        run(
+            "x=global; f() { local x=inner y; x=changed; echo \"$x [$y]\"; }; f; echo \"$x [$y]\"\n"
+        ),
+        "changed []\nglobal []\n"
+    );
+    assert_eq!(
+        run("r() { while true; do return 3; done; echo no; }; r || echo failed\n"),
+        "failed\n"
+    );
+    assert_eq!(
+        run("each() { for a; do echo $a; done; }; each p q\n"),
+        "p\nq\n"
+    );
+    assert_eq!(
+        run(
+            "fact() { if (($1 <= 1)); then echo 1; else echo $(($1 * $(fact $(($1 - 1))))); fi; }; fact 5\n"
+        ),
+        "120\n"
+    );
+    assert_eq!(
+        run("deep() { deep; }; deep\n"),
+        "deep: maximum function nesting level exceeded (200)\n"
+    );
+    assert_eq!(
+        run("return; local z\n"),
+        "return: can only `return' from a function or sourced script\nlocal: can only be used in a function\n"
+    );
+    assert_eq!(
+        run("a() { :; }\nfunction b { echo; }\nfunctions; functions c\n"),
+        "a() { :; }\nfunction b { echo; }\nfunctions: c: not found\n"
+    );
+}
+
+/// Runs wsh with `args` and returns everything it printed and its exit
+/// code.
+fn run_args(args: &[&str]) -> (String, i32) {
+    let (child, reader) = spawn(args, Stdio::null());
+    finish(child, reader)
+}
+
+#[test]
+fn test_scripts() {
+    assert_eq!(
+        run_args(&["-c", "echo $0 $1 $@; let 0", "name", "a", "b"]),
+        ("name a a b\n".to_owned(), 1)
+    );
+    assert_eq!(run_args(&["-c", "echo $0"]), ("wsh\n".to_owned(), 0));
+    assert_eq!(run_args(&["-c", "( )"]).1, 2, "a syntax error exits with 2");
+
+    let dir = std::env::temp_dir().join(format!("wsh-scripts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.wsh");
+    std::fs::write(
+        &script,
+        "#!/usr/bin/env wsh\necho $0 $1\ncat <<EOF\n$2\nEOF\nlet 0\n",
+    )
+    .unwrap();
+    let script = script.to_str().unwrap();
+    assert_eq!(
+        run_args(&[script, "a", "b"]),
+        (format!("{script} a\nb\n"), 1)
+    );
+
+    let lib = dir.join("lib.wsh");
+    std::fs::write(
+        &lib,
+        "greet() { echo hello $1; }\necho args $@\nreturn 3\necho no\n",
+    )
+    .unwrap();
+    let lib = lib.to_str().unwrap();
+    assert_eq!(
+        run(&format!(
+            "f() {{ . {lib} y z || echo returned; greet $1; }}\nf x\nsource\n"
+        )),
+        "args y z\nreturned\nhello x\nsource: filename argument required\n"
+    );
+    assert_eq!(
+        run("source /nonexistent/wsh\n"),
+        "source: /nonexistent/wsh: No such file or directory (os error 2)\n"
+    );
+
+    assert_eq!(
+        run_args(&["/nonexistent/wsh"]),
+        (
+            "wsh: /nonexistent/wsh: No such file or directory (os error 2)\n".to_owned(),
+            127
+        )
+    );
+    assert_eq!(run("echo a; let 0; exit\necho b\n"), "a\n");
+    // commands reading the script's stdin get the next line, and only it
+    assert_eq!(run("head -1\nhello\n"), "hello\n");
+    assert_eq!(
+        run("sh -c 'read x; echo got $x'\nhello\necho after\n"),
+        "got hello\nafter\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_comments_and_continuation() {
+    assert_eq!(
+        run("# a comment\necho a # b\necho c#d '#e'\n"),
+        "a\nc#d #e\n"
+    );
+    assert_eq!(
+        run("for i in 1 2\ndo\n  echo $i\ndone\nif true\nthen echo yes\nfi\n"),
+        "1\n2\nyes\n"
+    );
+    assert_eq!(run("echo 'a\nb' |\ntr a-z A-Z\n"), "A\nB\n");
+    assert_eq!(run("true &&\necho and\n"), "and\n");
+    assert_eq!(run("f() {\n  echo in f\n}\nf\n"), "in f\n");
+}
+
+#[test]
+fn test_export_and_unset() {
+    assert_eq!(
+        run("FOO=1\nsh -c 'echo ${FOO:-none}'\nexport FOO\nsh -c 'echo $FOO'\n"),
+        "none\n1\n"
+    );
+    assert_eq!(
+        run("export A=x B=\"it's\"\nexport -p | grep -e '^export [AB]='\n"),
+        "export A='x'\nexport B='it'\\''s'\n"
+    );
+    assert_eq!(
+        run(
+            "export A=x\nexport -n A\nsh -c 'echo ${A:-none}'; echo $A\nunset A\necho ${A:-unset}\n"
+        ),
+        "none\nx\nunset\n"
+    );
+    assert_eq!(
+        run("PATH=/nonexistent\nls\nPATH=/usr/bin:/bin\nls -d /\n"),
+        "command not found: ls\n/\n"
+    );
+    assert_eq!(
+        run("f() { echo f; }\nunset -f f\nf\nunset 1x\nexport =y\n"),
+        "command not found: f\nunset: `1x': not a valid identifier\nexport: `=y': not a valid identifier\n"
+    );
+    assert_eq!(
+        run("f() { local HOME=/; export HOME; sh -c 'echo $HOME'; }\nf\ncd\npwd\n"),
+        format!("/\n{}\n", std::env::var("HOME").unwrap())
+    );
+}
+
+#[test]
+fn test_assignment_prefixes() {
+    assert_eq!(
+        run("FOO=bar sh -c 'echo $FOO'\necho ${FOO:-unset}\n"),
+        "bar\nunset\n"
+    );
+    assert_eq!(
+        run("a=1 b=$a env | grep '^[ab]='\nx=old\nx=new echo $x\necho $x\n"),
+        "a=1\nb=1\nold\nold\n"
+    );
+    assert_eq!(
+        run("f() { echo f:$y; sh -c 'echo sh:$y'; }\ny=tmp f\necho ${y-unset}\n"),
+        "f:tmp\nsh:tmp\nunset\n"
+    );
+    assert_eq!(
+        run("export E=outer\nE=inner sh -c 'echo $E'\nsh -c 'echo $E'\n"),
+        "inner\nouter\n"
+    );
+    assert_eq!(run("a=1 b=$a\necho $a$b\n"), "11\n");
+    assert_eq!(
+        run("=foo\n1x=2\n"),
+        "command not found: =foo\ncommand not found: 1x=2\n"
+    );
+}
+
+#[test]
+fn test_special_parameters() {
+    let (output, _) = run_args(&[
+        "-c",
+        "echo $0 $# \"$*\"; printf '[%s]' \"$@\"; echo; shift; echo $1; shift 5; echo $? $#",
+        "name",
+        "a b",
+        "",
+        "c",
+    ]);
+    assert_eq!(
+        output,
+        "name 3 a b  c\n[a b][][c]\n\nshift: 5: shift count out of range\n1 2\n"
+    );
+
+    assert_eq!(
+        run("set -- x 'y z'\nfor a in \"$@\"; do echo $a; done\nset --\necho $#\n"),
+        "x\ny z\n0\n"
+    );
+    assert_eq!(run("let 0\necho $?\necho $?\n"), "1\n0\n");
+    assert_eq!(run("f() { let 0; return; }\nf\necho $?\n"), "1\n");
+    assert_eq!(
+        run("echo $$ $(echo $$) | tr ' ' '\\n' | uniq | wc -l\n"),
+        "1\n"
+    );
+    assert_eq!(run("sleep 0 &\n[ $! -gt 0 ] && echo pid\nwait\n"), "pid\n");
+    assert_eq!(run("set -C\necho $-\n"), "C\n");
+}
+
+#[test]
+fn test_pipestatus() {
+    assert_eq!(
+        run(
+            "true | sh -c 'exit 3' | true\necho $? ${PIPESTATUS[@]} ${PIPESTATUS[1]} $PIPESTATUS\n"
+        ),
+        "0 0 3 0 3 0\n"
+    );
+    assert_eq!(
+        run("true | let 0\necho ${PIPESTATUS[*]}\n! true\necho $? ${PIPESTATUS[0]}\n"),
+        "0 1\n1 0\n"
+    );
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(run_args(&["-c", "false"]), (String::new(), 1));
+    assert_eq!(run_args(&["-c", "exit 7; echo no"]), (String::new(), 7));
+    assert_eq!(run_args(&["-c", "let 0; exit"]), (String::new(), 1));
+    assert_eq!(
+        run_args(&["-c", "exit x"]),
+        ("exit: x: numeric argument required\n".to_owned(), 2)
+    );
+    assert_eq!(
+        run_args(&["-c", "sh -c 'kill -9 $$'; echo $?; (exit 3); echo $?"]),
+        ("137\n3\n".to_owned(), 0)
+    );
+    assert_eq!(run("f() { exit 4; }\nf\necho no\n"), "");
+
+    // builtins fail too
+    assert_eq!(
+        run("cd /nope && echo no\necho $?\npwd x\necho $?\nshopt nope\necho $?\nget\necho $?\n"),
+        "cd: operation failed: No such file or directory (os error 2)\n1\n\
+         pwd: too many arguments\n1\n\
+         shopt: nope: invalid shell option name\n1\n\
+         get: expected key\n1\n"
+    );
+    assert_eq!(run_args(&["-c", "set -e; cd /nope; echo after"]).1, 1);
+}
+
+#[test]
+fn test_errexit() {
+    let input = "set -e\nfalse || echo or\nif false; then :; fi\nwhile false; do :; done\n! true\nfalse && echo no\nf() { false; echo in f; }\nf && echo tested\necho before\nfalse\necho after\n";
+    assert_eq!(
+        run_args(&["-c", input]),
+        ("or\nin f\ntested\nbefore\n".to_owned(), 1)
+    );
+    assert_eq!(run_args(&["-c", "set -e; (exit 3); echo no"]).1, 3);
+    assert_eq!(run_args(&["-c", "set -e; true && false; echo no"]).1, 1);
+    assert_eq!(run("set -e\nset +e\nfalse\necho on\n"), "on\n");
+}
+
+#[test]
+fn test_nounset() {
+    assert_eq!(
+        run_args(&[
+            "-c",
+            "set -u; echo ${x-default} ${x:+alt} $# \"$@\"; echo $x; echo no"
+        ]),
+        ("default 0\nx: unbound variable\n".to_owned(), 127)
+    );
+    assert_eq!(
+        run_args(&["-c", "set -u; echo ${#y}", "name"]),
+        ("y: unbound variable\n".to_owned(), 127)
+    );
+    // every expansion stops a script, arithmetic included
+    for command in [
+        "for i in $nope; do :; done",
+        "case $nope in *) ;; esac",
+        "echo $((nope + 1))",
+        "let x=nope+1",
+        "((nope))",
+    ] {
+        let (output, status) = run_args(&["-c", &format!("set -u; {command}; echo after")]);
+        assert!(
+            output.ends_with("nope: unbound variable\n"),
+            "{command}: {output:?}"
+        );
+        assert_eq!(status, 127, "{command}");
+    }
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    // a bad `$((...))` stops a script, as `${x?}` does
+    for (command, error) in [
+        ("x=$((1/0))", "division by 0"),
+        ("echo $((1 +))", "syntax error: operand expected"),
+        ("echo $((2 ** -1))", "exponent less than 0"),
+    ] {
+        assert_eq!(
+            run_args(&["-c", &format!("{command}; echo after")]),
+            (format!("arithmetic error: {error}\n"), 127),
+            "{command}"
+        );
+    }
+    // while `let` and `((...))` only fail
+    assert_eq!(
+        run_args(&["-c", "let x=1/0; ((1/0)); echo after $?"]),
+        (
+            "let: x=1/0: division by 0\n((: arithmetic error: division by 0\nafter 1\n".to_owned(),
+            0
+        )
+    );
+}
+
+#[test]
+fn test_pipefail() {
+    assert_eq!(
+        run(
+            "sh -c 'exit 2' | true\necho $?\nset -o pipefail\nsh -c 'exit 2' | sh -c 'exit 3' | true\necho $?\ntrue | true\necho $?\n"
+        ),
+        "0\n3\n0\n"
+    );
+}
+
+#[test]
+fn test_set_options() {
+    assert_eq!(
+        run("set -o\n"),
+        "errexit\toff\nnoclobber\toff\nnounset\toff\npipefail\toff\nxtrace\toff\n"
+    );
+    assert_eq!(
+        run("set -eu -o pipefail\nset +e\nset +o\necho $-\n"),
+        "set +o errexit\nset +o noclobber\nset -o nounset\nset -o pipefail\nset +o xtrace\nu\n"
+    );
+    assert_eq!(run("set -q\necho $?\n"), "set: -q: invalid option\n2\n");
+    assert_eq!(
+        run("set -o nope\necho $?\n"),
+        "set: nope: invalid option name\n1\n"
+    );
+    assert_eq!(
+        run("{ set -x; a='b c' echo \"$a\" x; y=1; set +x; } 2>&1\n"),
+        "+ a='b c' echo '' x\n x\n+ y=1\n+ set +x\n"
+    );
+}
+
+#[test]
+fn test_xtrace_ps4() {
+    let input = "PS4='+ ${FUNCNAME:-main}:$LINENO: '\nf() {\n  echo \"$@\"\n}\n{ set -x; f a 'b c'; set +x; } 2>&1\necho $LINENO\n";
+    assert_eq!(
+        run(input),
+        "+ main:5: f a 'b c'\n+ f:3: echo a 'b c'\na b c\n+ main:5: set +x\n6\n"
+    );
+}
+
+#[test]
+fn test_debugger() {
+    let dir = std::env::temp_dir().join(format!("wsh-debug-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.wsh");
+    std::fs::write(
+        &script,
+        "x=1\nf() {\n  echo in f $1\n}\nf a\nf b\necho done $x\n",
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wsh"))
+        .arg("--debug")
+        .arg(&script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start wsh");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"n\np $x\n\nb 6\nc\ns\np $1 $LINENO\nc\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let script = script.to_str().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "in f a\nin f b\ndone 1\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{script}:1: x=1\n(debug) {script}:2: f() {{\n  echo in f $1\n}}\n\
+             (debug) 1\n(debug) {script}:5: f a\n(debug) breakpoint at line 6\n\
+             (debug) {script}:6: f b\n(debug) {script}:3: echo in f $1\n(debug) b 3\n(debug) "
+        )
+    );
+
+    assert_eq!(
+        run_args(&["--debug"]),
+        (
+            "wsh: --debug: the script must be a file or -c\n".to_owned(),
+            2
+        ),
+        "a script on standard input leaves nothing to read commands from"
+    );
+}